@@ -4,7 +4,7 @@ use std::ptr::addr_of;
 use http::HeaderMap;
 use ngx::core;
 use ngx::ffi::{
-    ngx_array_push, ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_handler_pt, ngx_http_module_t,
+    ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_handler_pt, ngx_http_module_t,
     ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE, ngx_int_t, ngx_module_t, ngx_str_t, ngx_uint_t, NGX_CONF_TAKE1,
     NGX_HTTP_LOC_CONF, NGX_HTTP_LOC_CONF_OFFSET, NGX_HTTP_MODULE, NGX_HTTP_SRV_CONF,
 };
@@ -21,13 +21,13 @@ impl HTTPModule for Module {
     unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
         let cmcf = ngx_http_conf_get_module_main_conf(cf, &*addr_of!(ngx_http_core_module));
 
-        let h = ngx_array_push(&mut (*cmcf).phases[ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE as usize].handlers)
-            as *mut ngx_http_handler_pt;
-        if h.is_null() {
+        let handlers = core::NgxArray::<ngx_http_handler_pt>::from_ngx_array_mut(
+            &mut (*cmcf).phases[ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE as usize].handlers,
+        );
+        // set an phase handler
+        if handlers.push(Some(awssigv4_header_handler)).is_err() {
             return core::Status::NGX_ERROR.into();
         }
-        // set an phase handler
-        *h = Some(awssigv4_header_handler);
         core::Status::NGX_OK.into()
     }
 }
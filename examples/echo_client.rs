@@ -0,0 +1,246 @@
+use std::ffi::{c_char, c_void};
+use std::io;
+use std::net::SocketAddr;
+use std::ptr::addr_of;
+use std::time::Duration;
+
+use ngx::core;
+use ngx::event::{PeerConnection, Resolver};
+use ngx::ffi::{
+    ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_module_t, ngx_int_t, ngx_module_t, ngx_parse_time,
+    ngx_str_t, ngx_uint_t, NGX_CONF_TAKE1, NGX_HTTP_LOC_CONF, NGX_HTTP_LOC_CONF_OFFSET, NGX_HTTP_MODULE, NGX_LOG_EMERG,
+};
+use ngx::http::{self, HTTPStatus, MergeConfigError, Method};
+use ngx::{http_request_handler, ngx_conf_log_error, ngx_log_debug_http, ngx_string};
+
+struct Module;
+
+impl http::HTTPModule for Module {
+    type MainConf = ();
+    type SrvConf = ();
+    type LocConf = ModuleConfig;
+}
+
+#[derive(Debug, Default)]
+struct ModuleConfig {
+    peer: Option<Peer>,
+    timeout: Option<Duration>,
+}
+
+/// Address of the echo server, either an IP address or a name resolved with the `resolver`.
+#[derive(Clone, Debug)]
+enum Peer {
+    Addr(SocketAddr),
+    Name(String, u16),
+}
+
+static mut NGX_HTTP_ECHO_CLIENT_COMMANDS: [ngx_command_t; 3] = [
+    ngx_command_t {
+        name: ngx_string!("echo_client"),
+        type_: (NGX_HTTP_LOC_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
+        set: Some(ngx_http_echo_client_set),
+        conf: NGX_HTTP_LOC_CONF_OFFSET,
+        offset: 0,
+        post: std::ptr::null_mut(),
+    },
+    ngx_command_t {
+        name: ngx_string!("echo_client_timeout"),
+        type_: (NGX_HTTP_LOC_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
+        set: Some(ngx_http_echo_client_timeout_set),
+        conf: NGX_HTTP_LOC_CONF_OFFSET,
+        offset: 0,
+        post: std::ptr::null_mut(),
+    },
+    ngx_command_t::empty(),
+];
+
+static NGX_HTTP_ECHO_CLIENT_MODULE_CTX: ngx_http_module_t = ngx_http_module_t {
+    preconfiguration: Some(Module::preconfiguration),
+    postconfiguration: Some(Module::postconfiguration),
+    create_main_conf: Some(Module::create_main_conf),
+    init_main_conf: Some(Module::init_main_conf),
+    create_srv_conf: Some(Module::create_srv_conf),
+    merge_srv_conf: Some(Module::merge_srv_conf),
+    create_loc_conf: Some(Module::create_loc_conf),
+    merge_loc_conf: Some(Module::merge_loc_conf),
+};
+
+// Generate the `ngx_modules` table with exported modules.
+// This feature is required to build a 'cdylib' dynamic module outside of the NGINX buildsystem.
+#[cfg(feature = "export-modules")]
+ngx::ngx_modules!(ngx_http_echo_client_module);
+
+#[used]
+#[allow(non_upper_case_globals)]
+#[cfg_attr(not(feature = "export-modules"), no_mangle)]
+pub static mut ngx_http_echo_client_module: ngx_module_t = ngx_module_t {
+    ctx: std::ptr::addr_of!(NGX_HTTP_ECHO_CLIENT_MODULE_CTX) as _,
+    commands: unsafe { &NGX_HTTP_ECHO_CLIENT_COMMANDS[0] as *const _ as *mut _ },
+    type_: NGX_HTTP_MODULE as _,
+    ..ngx_module_t::default()
+};
+
+impl http::Merge for ModuleConfig {
+    fn merge(&mut self, prev: &ModuleConfig) -> Result<(), MergeConfigError> {
+        if self.peer.is_none() {
+            self.peer = prev.peer.clone();
+        }
+        if self.timeout.is_none() {
+            self.timeout = prev.timeout;
+        }
+        Ok(())
+    }
+}
+
+http_request_handler!(echo_client_handler, |request: &mut http::Request| {
+    let co = unsafe { request.get_module_loc_conf::<ModuleConfig>(&*addr_of!(ngx_http_echo_client_module)) };
+    let co = co.expect("module config is none");
+    let peer = co.peer.clone().expect("echo client address is not configured");
+    let timeout = co.timeout.unwrap_or(Duration::from_secs(60));
+
+    if !matches!(request.method(), Method::GET | Method::HEAD) {
+        return HTTPStatus::NOT_ALLOWED.into();
+    }
+
+    let rc = request.discard_request_body();
+    if rc != core::Status::NGX_OK {
+        return rc;
+    }
+
+    // the query string is sent as a line and the response is expected to be a line
+    let mut message = request.get_inner().args.as_bytes().to_vec();
+    message.push(b'\n');
+
+    ngx_log_debug_http!(request, "echo client: connecting to {:?}", peer);
+
+    let resolver = request.core_loc_conf().resolver();
+    let Some(request) = http::RequestRef::new(request) else {
+        return core::Status::NGX_ERROR;
+    };
+
+    // the future is polled on the nginx thread and finalizes the request when done;
+    // it is canceled if the request is terminated before, e.g. on a client abort
+    request.spawn(|mut request| async move {
+        let rc = echo(&mut request, peer, resolver, timeout, &message).await;
+        request.finalize(rc);
+    });
+
+    core::Status::NGX_DONE
+});
+
+async fn echo(
+    request: &mut http::Request,
+    peer: Peer,
+    resolver: Resolver,
+    timeout: Duration,
+    message: &[u8],
+) -> ngx_int_t {
+    let response = match exchange(peer, resolver, timeout, message).await {
+        Ok(response) => response,
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => return HTTPStatus::GATEWAY_TIME_OUT.0 as _,
+        Err(_) => return HTTPStatus::BAD_GATEWAY.0 as _,
+    };
+
+    ngx_log_debug_http!(request, "echo client: {} bytes received", response.len());
+
+    request.set_status(HTTPStatus::OK);
+    request.set_content_length_n(response.len());
+
+    let rc = request.send_header();
+    if rc == core::Status::NGX_ERROR || rc > core::Status::NGX_OK || request.header_only() {
+        return rc.0;
+    }
+
+    let mut chain = core::Chain::new(request.pool());
+    if chain.push_bytes(&response).is_none() || chain.set_last_buf(request.is_main()).is_none() {
+        return core::Status::NGX_ERROR.0;
+    }
+
+    let out = chain.as_ngx_chain();
+    request.output_filter(unsafe { &mut *out }).0
+}
+
+async fn exchange(peer: Peer, resolver: Resolver, timeout: Duration, message: &[u8]) -> io::Result<Vec<u8>> {
+    let addr = match peer {
+        Peer::Addr(addr) => addr,
+        Peer::Name(name, port) => {
+            let addrs = resolver.resolve(name.as_bytes()).await.map_err(|err| {
+                let kind = if err.is_timeout() {
+                    io::ErrorKind::TimedOut
+                } else {
+                    io::ErrorKind::Other
+                };
+                io::Error::new(kind, err)
+            })?;
+
+            let mut addr = *addrs.first().ok_or_else(|| io::Error::other("no addresses"))?;
+            addr.set_port(port);
+            addr
+        }
+    };
+
+    let mut peer = PeerConnection::connect(addr, timeout).await?;
+    peer.set_read_timeout(Some(timeout));
+    peer.set_write_timeout(Some(timeout));
+
+    peer.write_all(message).await?;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !response.ends_with(b"\n") {
+        let n = peer.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    Ok(response)
+}
+
+extern "C" fn ngx_http_echo_client_set(
+    cf: *mut ngx_conf_t,
+    _cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    unsafe {
+        let conf = &mut *(conf as *mut ModuleConfig);
+        let args = (*(*cf).args).elts as *mut ngx_str_t;
+
+        let value = (*args.add(1)).to_str();
+        if let Ok(addr) = value.parse() {
+            conf.peer = Some(Peer::Addr(addr));
+        } else if let Some((name, port)) = value.rsplit_once(':').and_then(|(n, p)| Some((n, p.parse().ok()?))) {
+            conf.peer = Some(Peer::Name(name.to_string(), port));
+        } else {
+            ngx_conf_log_error!(NGX_LOG_EMERG, cf, "invalid address \"{}\"", value);
+            return ngx::core::NGX_CONF_ERROR as _;
+        }
+
+        let clcf = http::ngx_http_conf_get_module_loc_conf(cf, &*addr_of!(ngx_http_core_module));
+        (*clcf).handler = Some(echo_client_handler);
+    };
+
+    std::ptr::null_mut()
+}
+
+extern "C" fn ngx_http_echo_client_timeout_set(
+    cf: *mut ngx_conf_t,
+    _cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    unsafe {
+        let conf = &mut *(conf as *mut ModuleConfig);
+        let args = (*(*cf).args).elts as *mut ngx_str_t;
+
+        let msec = ngx_parse_time(args.add(1), 0);
+        if msec < 0 {
+            ngx_conf_log_error!(NGX_LOG_EMERG, cf, "invalid timeout \"{}\"", (*args.add(1)).to_str());
+            return ngx::core::NGX_CONF_ERROR as _;
+        }
+        conf.timeout = Some(Duration::from_millis(msec as u64));
+    };
+
+    std::ptr::null_mut()
+}
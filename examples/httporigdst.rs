@@ -1,17 +1,15 @@
 use std::ffi::{c_int, c_void};
+use std::net::SocketAddr;
 use std::ptr::addr_of;
 
 use ngx::core;
 use ngx::ffi::{
-    in_port_t, ngx_conf_t, ngx_connection_local_sockaddr, ngx_http_add_variable, ngx_http_module_t,
-    ngx_http_variable_t, ngx_inet_get_port, ngx_int_t, ngx_module_t, ngx_sock_ntop, ngx_str_t, ngx_variable_value_t,
-    sockaddr, sockaddr_storage, INET_ADDRSTRLEN, NGX_HTTP_MODULE,
+    in_port_t, ngx_conf_t, ngx_http_add_variable, ngx_http_module_t, ngx_http_variable_t, ngx_int_t, ngx_module_t,
+    ngx_str_t, ngx_variable_value_t, sockaddr_storage, NGX_HTTP_MODULE,
 };
 use ngx::http::{self, HTTPModule};
 use ngx::{http_variable_get, ngx_log_debug_http, ngx_string};
 
-const IPV4_STRLEN: usize = INET_ADDRSTRLEN as usize;
-
 #[derive(Debug, Default)]
 struct NgxHttpOrigDstCtx {
     orig_dst_addr: ngx_str_t,
@@ -19,7 +17,7 @@ struct NgxHttpOrigDstCtx {
 }
 
 impl NgxHttpOrigDstCtx {
-    pub fn save(&mut self, addr: &str, port: in_port_t, pool: &mut core::Pool) -> core::Status {
+    pub fn save(&mut self, addr: &str, port: in_port_t, pool: &core::Pool) -> core::Status {
         let addr_data = pool.alloc_unaligned(addr.len());
         if addr_data.is_null() {
             return core::Status::NGX_ERROR;
@@ -119,25 +117,27 @@ static mut NGX_HTTP_ORIG_DST_VARS: [ngx_http_variable_t; 2] = [
 ];
 
 unsafe fn ngx_get_origdst(request: &mut http::Request) -> Result<(String, in_port_t), core::Status> {
-    let c = request.connection();
+    let c = request.client_connection_mut();
+    let (sock_type, fd, local_addr) = (c.get_inner().type_, c.fd(), c.local_addr());
+    // the address is not cached if getsockname() failed
+    let no_local_addr = c.get_inner().local_sockaddr.is_null();
 
-    if (*c).type_ != libc::SOCK_STREAM {
+    if sock_type != libc::SOCK_STREAM {
         ngx_log_debug_http!(request, "httporigdst: connection is not type SOCK_STREAM");
         return Err(core::Status::NGX_DECLINED);
     }
 
-    if ngx_connection_local_sockaddr(c, std::ptr::null_mut(), 0) != core::Status::NGX_OK.into() {
-        ngx_log_debug_http!(request, "httporigdst: no local sockaddr from connection");
-        return Err(core::Status::NGX_ERROR);
-    }
-
     let level: c_int;
     let optname: c_int;
-    match (*(*c).local_sockaddr).sa_family as i32 {
-        libc::AF_INET => {
+    match local_addr {
+        Some(SocketAddr::V4(_)) => {
             level = libc::SOL_IP;
             optname = libc::SO_ORIGINAL_DST;
         }
+        None if no_local_addr => {
+            ngx_log_debug_http!(request, "httporigdst: no local sockaddr from connection");
+            return Err(core::Status::NGX_ERROR);
+        }
         _ => {
             ngx_log_debug_http!(request, "httporigdst: only support IPv4");
             return Err(core::Status::NGX_DECLINED);
@@ -147,7 +147,7 @@ unsafe fn ngx_get_origdst(request: &mut http::Request) -> Result<(String, in_por
     let mut addr: sockaddr_storage = { std::mem::zeroed() };
     let mut addrlen: libc::socklen_t = std::mem::size_of_val(&addr) as libc::socklen_t;
     let rc = libc::getsockopt(
-        (*c).fd,
+        fd,
         level,
         optname,
         &mut addr as *mut _ as *mut _,
@@ -157,25 +157,14 @@ unsafe fn ngx_get_origdst(request: &mut http::Request) -> Result<(String, in_por
         ngx_log_debug_http!(request, "httporigdst: getsockopt failed");
         return Err(core::Status::NGX_DECLINED);
     }
-    let mut ip: Vec<u8> = vec![0; IPV4_STRLEN];
-    let e = unsafe {
-        ngx_sock_ntop(
-            std::ptr::addr_of_mut!(addr) as *mut sockaddr,
-            std::mem::size_of::<sockaddr>() as u32,
-            ip.as_mut_ptr(),
-            IPV4_STRLEN,
-            0,
-        )
-    };
-    if e == 0 {
-        ngx_log_debug_http!(request, "httporigdst: ngx_sock_ntop failed to convert sockaddr");
-        return Err(core::Status::NGX_ERROR);
-    }
-    ip.truncate(e);
-
-    let port = unsafe { ngx_inet_get_port(std::ptr::addr_of_mut!(addr) as *mut sockaddr) };
 
-    Ok((String::from_utf8(ip).unwrap(), port))
+    match core::sockaddr_to_socket_addr(std::ptr::addr_of!(addr).cast(), addrlen) {
+        Some(addr) => Ok((addr.ip().to_string(), addr.port())),
+        None => {
+            ngx_log_debug_http!(request, "httporigdst: unsupported original destination family");
+            Err(core::Status::NGX_DECLINED)
+        }
+    }
 }
 
 http_variable_get!(
@@ -208,7 +197,7 @@ http_variable_get!(
                 }
 
                 ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
+                (*new_ctx).save(&ip, port, request.pool());
                 (*new_ctx).bind_addr(v);
                 request.set_module_ctx(new_ctx as *mut c_void, &*addr_of!(ngx_http_orig_dst_module));
             }
@@ -247,7 +236,7 @@ http_variable_get!(
                 }
 
                 ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
+                (*new_ctx).save(&ip, port, request.pool());
                 (*new_ctx).bind_port(v);
                 request.set_module_ctx(new_ctx as *mut c_void, &*addr_of!(ngx_http_orig_dst_module));
             }
@@ -0,0 +1,194 @@
+use std::ffi::{c_char, c_void};
+use std::ptr::addr_of;
+
+use ngx::core;
+use ngx::ffi::{
+    ngx_cached_time, ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_map_uri_to_path, ngx_http_module_t,
+    ngx_module_t, ngx_str_t, ngx_uint_t, time_t, NGX_CONF_FLAG, NGX_CONF_TAKE1, NGX_HTTP_LOC_CONF,
+    NGX_HTTP_LOC_CONF_OFFSET, NGX_HTTP_MODULE, NGX_LOG_EMERG,
+};
+use ngx::http::{self, FileResponse, HTTPStatus, MergeConfigError, Method};
+use ngx::{http_request_handler, ngx_conf_log_error, ngx_log_debug_http, ngx_string};
+
+struct Module;
+
+impl http::HTTPModule for Module {
+    type MainConf = ();
+    type SrvConf = ();
+    type LocConf = ModuleConfig;
+}
+
+#[derive(Debug, Default)]
+struct ModuleConfig {
+    text: Option<ngx_str_t>,
+    mtime: time_t,
+}
+
+static mut NGX_HTTP_STATIC_CONTENT_COMMANDS: [ngx_command_t; 3] = [
+    ngx_command_t {
+        name: ngx_string!("static_text"),
+        type_: (NGX_HTTP_LOC_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
+        set: Some(ngx_http_static_text_set),
+        conf: NGX_HTTP_LOC_CONF_OFFSET,
+        offset: 0,
+        post: std::ptr::null_mut(),
+    },
+    ngx_command_t {
+        name: ngx_string!("static_file"),
+        type_: (NGX_HTTP_LOC_CONF | NGX_CONF_FLAG) as ngx_uint_t,
+        set: Some(ngx_http_static_file_set),
+        conf: NGX_HTTP_LOC_CONF_OFFSET,
+        offset: 0,
+        post: std::ptr::null_mut(),
+    },
+    ngx_command_t::empty(),
+];
+
+static NGX_HTTP_STATIC_CONTENT_MODULE_CTX: ngx_http_module_t = ngx_http_module_t {
+    preconfiguration: Some(Module::preconfiguration),
+    postconfiguration: Some(Module::postconfiguration),
+    create_main_conf: Some(Module::create_main_conf),
+    init_main_conf: Some(Module::init_main_conf),
+    create_srv_conf: Some(Module::create_srv_conf),
+    merge_srv_conf: Some(Module::merge_srv_conf),
+    create_loc_conf: Some(Module::create_loc_conf),
+    merge_loc_conf: Some(Module::merge_loc_conf),
+};
+
+// Generate the `ngx_modules` table with exported modules.
+// This feature is required to build a 'cdylib' dynamic module outside of the NGINX buildsystem.
+#[cfg(feature = "export-modules")]
+ngx::ngx_modules!(ngx_http_static_content_module);
+
+#[used]
+#[allow(non_upper_case_globals)]
+#[cfg_attr(not(feature = "export-modules"), no_mangle)]
+pub static mut ngx_http_static_content_module: ngx_module_t = ngx_module_t {
+    ctx: std::ptr::addr_of!(NGX_HTTP_STATIC_CONTENT_MODULE_CTX) as _,
+    commands: unsafe { &NGX_HTTP_STATIC_CONTENT_COMMANDS[0] as *const _ as *mut _ },
+    type_: NGX_HTTP_MODULE as _,
+    ..ngx_module_t::default()
+};
+
+impl http::Merge for ModuleConfig {
+    fn merge(&mut self, prev: &ModuleConfig) -> Result<(), MergeConfigError> {
+        if self.text.is_none() {
+            self.text = prev.text;
+            self.mtime = prev.mtime;
+        }
+        Ok(())
+    }
+}
+
+http_request_handler!(static_text_handler, |request: &mut http::Request| {
+    let co = unsafe { request.get_module_loc_conf::<ModuleConfig>(&*addr_of!(ngx_http_static_content_module)) };
+    let co = co.expect("module config is none");
+    let (text, mtime) = (co.text.expect("static text is not configured"), co.mtime);
+
+    if !matches!(request.method(), Method::GET | Method::HEAD) {
+        return HTTPStatus::NOT_ALLOWED.into();
+    }
+
+    let rc = request.discard_request_body();
+    if rc != core::Status::NGX_OK {
+        return rc;
+    }
+
+    ngx_log_debug_http!(request, "static text: {} bytes", text.len);
+
+    // Range, If-Range, If-Modified-Since and If-None-Match are handled by the range and
+    // not modified filters, as long as the length, modification time and ETag are known.
+    request.set_status(HTTPStatus::OK);
+    request.set_content_length_n(text.len);
+    request.set_last_modified_time(mtime);
+    request.set_allow_ranges(true);
+
+    if request.set_etag() != core::Status::NGX_OK || request.set_content_type() != core::Status::NGX_OK {
+        return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+    }
+
+    let rc = request.send_header();
+    if rc == core::Status::NGX_ERROR || rc > core::Status::NGX_OK || request.header_only() {
+        return rc;
+    }
+
+    let mut chain = core::Chain::new(request.pool());
+    if chain.push_bytes(text.as_bytes()).is_none() || chain.set_last_buf(request.is_main()).is_none() {
+        return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+    }
+
+    let out = chain.as_ngx_chain();
+    request.output_filter(unsafe { &mut *out })
+});
+
+http_request_handler!(static_file_handler, |request: &mut http::Request| {
+    if !matches!(request.method(), Method::GET | Method::HEAD) {
+        return HTTPStatus::NOT_ALLOWED.into();
+    }
+
+    if request.path().as_bytes().ends_with(b"/") {
+        return core::Status::NGX_DECLINED;
+    }
+
+    let mut root = ngx_str_t::empty();
+    let mut path = ngx_str_t::empty();
+    let last = unsafe { ngx_http_map_uri_to_path(request.into(), &mut path, &mut root, 0) };
+    if last.is_null() {
+        return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+    }
+    path.len = unsafe { last.offset_from(path.data) } as usize;
+
+    ngx_log_debug_http!(request, "static file: \"{}\"", path.as_bytes().escape_ascii());
+
+    match FileResponse::open(request, path.as_bytes()) {
+        Ok(file) => file.send(request),
+        Err(status) => status.into(),
+    }
+});
+
+extern "C" fn ngx_http_static_text_set(
+    cf: *mut ngx_conf_t,
+    _cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    unsafe {
+        let conf = &mut *(conf as *mut ModuleConfig);
+        let args = (*(*cf).args).elts as *mut ngx_str_t;
+
+        // the arguments are allocated from the configuration pool and outlive the location
+        conf.text = Some(*args.add(1));
+        conf.mtime = (*ngx_cached_time).sec;
+
+        let clcf = http::ngx_http_conf_get_module_loc_conf(cf, &*addr_of!(ngx_http_core_module));
+        (*clcf).handler = Some(static_text_handler);
+    };
+
+    std::ptr::null_mut()
+}
+
+extern "C" fn ngx_http_static_file_set(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    _conf: *mut c_void,
+) -> *mut c_char {
+    unsafe {
+        let args = (*(*cf).args).elts as *mut ngx_str_t;
+        let value = (*args.add(1)).to_str();
+
+        if value.eq_ignore_ascii_case("on") {
+            let clcf = http::ngx_http_conf_get_module_loc_conf(cf, &*addr_of!(ngx_http_core_module));
+            (*clcf).handler = Some(static_file_handler);
+        } else if !value.eq_ignore_ascii_case("off") {
+            ngx_conf_log_error!(
+                NGX_LOG_EMERG,
+                cf,
+                "invalid value \"{}\" in \"{}\" directive, it must be \"on\" or \"off\"",
+                value,
+                (*cmd).name.to_str()
+            );
+            return core::NGX_CONF_ERROR as _;
+        }
+    };
+
+    std::ptr::null_mut()
+}
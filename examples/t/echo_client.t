@@ -0,0 +1,203 @@
+#!/usr/bin/perl
+
+# (C) Nginx, Inc
+
+# Tests for ngx-rust example modules.
+
+###############################################################################
+
+use warnings;
+use strict;
+
+use Test::More;
+
+use IO::Select;
+use IO::Socket::INET;
+
+BEGIN { use FindBin; chdir($FindBin::Bin); }
+
+use lib 'lib';
+use Test::Nginx;
+
+###############################################################################
+
+select STDERR; $| = 1;
+select STDOUT; $| = 1;
+
+my $t = Test::Nginx->new()->has(qw/http/)->plan(5)
+	->write_file_expand('nginx.conf', <<"EOF");
+
+%%TEST_GLOBALS%%
+
+daemon off;
+
+events {
+}
+
+http {
+    %%TEST_GLOBALS_HTTP%%
+
+    server {
+        listen       127.0.0.1:8080;
+        server_name  localhost;
+
+        location /echo {
+            echo_client 127.0.0.1:8081;
+        }
+
+        location /refused {
+            echo_client 127.0.0.1:8082;
+        }
+
+        location /timeout {
+            echo_client 127.0.0.1:8083;
+            echo_client_timeout 1s;
+        }
+
+        location /name {
+            resolver 127.0.0.1:8981;
+            resolver_timeout 1s;
+            echo_client echo.example.com:8081;
+        }
+
+        location /nxdomain {
+            resolver 127.0.0.1:8981;
+            resolver_timeout 1s;
+            echo_client nx.example.com:8081;
+        }
+    }
+}
+
+EOF
+
+$t->run_daemon(\&echo_daemon, 8081);
+$t->run_daemon(\&silent_daemon, 8083);
+$t->run_daemon(\&dns_daemon, 8981, $t);
+$t->run();
+
+$t->waitforfile($t->testdir . '/8981');
+
+$t->waitforsocket('127.0.0.1:8081');
+$t->waitforsocket('127.0.0.1:8083');
+
+###############################################################################
+
+like(http_get('/echo?hello'), qr/200 OK.*\x0d\x0a\x0d\x0ahello$/ms, 'echo');
+like(http_get('/refused?hello'), qr/502 Bad/, 'connection refused');
+like(http_get('/timeout?hello'), qr/504 Gateway/, 'read timeout');
+like(http_get('/name?hello'), qr/200 OK.*\x0d\x0a\x0d\x0ahello$/ms,
+	'resolved name');
+like(http_get('/nxdomain?hello'), qr/502 Bad/, 'unknown name');
+
+###############################################################################
+
+sub echo_daemon {
+	my ($port) = @_;
+
+	my $server = IO::Socket::INET->new(
+		Proto => 'tcp',
+		LocalAddr => "127.0.0.1:$port",
+		Listen => 5,
+		Reuse => 1
+	)
+		or die "Can't create listening socket: $!\n";
+
+	local $SIG{PIPE} = 'IGNORE';
+
+	while (my $client = $server->accept()) {
+		$client->autoflush(1);
+
+		my $line = <$client>;
+		print $client $line if defined $line;
+
+		close $client;
+	}
+}
+
+sub silent_daemon {
+	my ($port) = @_;
+
+	my $server = IO::Socket::INET->new(
+		Proto => 'tcp',
+		LocalAddr => "127.0.0.1:$port",
+		Listen => 5,
+		Reuse => 1
+	)
+		or die "Can't create listening socket: $!\n";
+
+	my $sel = IO::Select->new($server);
+	my @clients;
+
+	# accept the connections and never respond
+
+	while ($sel->can_read()) {
+		push @clients, $server->accept();
+	}
+}
+
+sub reply_handler {
+	my ($recv_data) = @_;
+
+	my (@name, @rdata);
+
+	use constant NOERROR	=> 0;
+	use constant NXDOMAIN	=> 3;
+	use constant A		=> 1;
+	use constant IN		=> 1;
+
+	# default values
+
+	my ($hdr, $rcode, $ttl) = (0x8180, NOERROR, 3600);
+
+	# decode name
+
+	my ($len, $offset) = (undef, 12);
+	while (1) {
+		$len = unpack("\@$offset C", $recv_data);
+		last if $len == 0;
+		$offset++;
+		push @name, unpack("\@$offset A$len", $recv_data);
+		$offset += $len;
+	}
+
+	$offset -= 1;
+	my ($id, $type, $class) = unpack("n x$offset n2", $recv_data);
+
+	my $name = join('.', @name);
+	if ($name eq 'echo.example.com') {
+		push @rdata, pack('n3N n C4', 0xc00c, A, IN, $ttl, 4,
+			split(/\./, '127.0.0.1')) if $type == A;
+
+	} else {
+		$rcode = NXDOMAIN;
+	}
+
+	$len = @name;
+	pack("n6 (C/a*)$len x n2", $id, $hdr | $rcode, 1, scalar @rdata,
+		0, 0, @name, $type, $class) . join('', @rdata);
+}
+
+sub dns_daemon {
+	my ($port, $t) = @_;
+
+	my ($data, $recv_data);
+	my $socket = IO::Socket::INET->new(
+		LocalAddr => '127.0.0.1',
+		LocalPort => $port,
+		Proto => 'udp',
+	)
+		or die "Can't create listening socket: $!\n";
+
+	# signal we are ready
+
+	open my $fh, '>', $t->testdir() . '/' . $port;
+	close $fh;
+
+	while (1) {
+		$socket->recv($recv_data, 65536);
+		$data = reply_handler($recv_data);
+		$socket->send($data);
+	}
+}
+
+###############################################################################
@@ -0,0 +1,99 @@
+#!/usr/bin/perl
+
+# (C) Nginx, Inc
+
+# Tests for ngx-rust example modules.
+
+###############################################################################
+
+use warnings;
+use strict;
+
+use Test::More;
+
+BEGIN { use FindBin; chdir($FindBin::Bin); }
+
+use lib 'lib';
+use Test::Nginx;
+
+###############################################################################
+
+select STDERR; $| = 1;
+select STDOUT; $| = 1;
+
+my $t = Test::Nginx->new()->has(qw/http/)->plan(11)
+	->write_file_expand('nginx.conf', <<"EOF");
+
+%%TEST_GLOBALS%%
+
+daemon off;
+
+events {
+}
+
+http {
+    %%TEST_GLOBALS_HTTP%%
+
+    server {
+        listen       127.0.0.1:8080;
+        server_name  localhost;
+
+        location = /text {
+            static_text 0123456789;
+        }
+
+        location / {
+            static_file on;
+        }
+    }
+}
+
+EOF
+
+$t->write_file('file.html', 'abcdefghij');
+$t->run();
+
+###############################################################################
+
+my $r = http_get('/text');
+like($r, qr/200 OK.*0123456789$/s, 'text');
+
+my ($etag) = $r =~ /^ETag: (.+?)\x0d?$/mi;
+my ($lm) = $r =~ /^Last-Modified: (.+?)\x0d?$/mi;
+ok($etag && $lm, 'text validators');
+
+like(get('/text', 'Range: bytes=2-4'), qr/206 Partial.*\x0d\x0a234$/s,
+	'text range');
+like(get('/text', "If-None-Match: $etag"), qr/304 Not Modified/,
+	'text if-none-match');
+like(get('/text', "If-Modified-Since: $lm"), qr/304 Not Modified/,
+	'text if-modified-since');
+like(get('/text', 'Range: bytes=2-4', 'If-Range: "foo"'),
+	qr/200 OK.*0123456789$/s, 'text if-range mismatch');
+
+$r = http_get('/file.html');
+like($r, qr/200 OK.*abcdefghij$/s, 'file');
+($etag) = $r =~ /^ETag: (.+?)\x0d?$/mi;
+
+like(get('/file.html', 'Range: bytes=-3'), qr/206 Partial.*\x0d\x0ahij$/s,
+	'file range');
+like(get('/file.html', "If-None-Match: $etag"), qr/304 Not Modified/,
+	'file if-none-match');
+like(http_head('/file.html'), qr/Content-Length: 10.*\x0d\x0a\x0d\x0a$/s,
+	'file head');
+like(http_get('/missing.html'), qr/404 Not Found/, 'file not found');
+
+###############################################################################
+
+sub get {
+	my ($url, @headers) = @_;
+	my $extra = join '', map { "$_\n" } @headers;
+	return http(<<EOF);
+GET $url HTTP/1.1
+Host: localhost
+Connection: close
+${extra}
+EOF
+}
+
+###############################################################################
@@ -0,0 +1,7 @@
+//! The types are re-exported from the [`allocator_api2`] crate, which provides a stable version of
+//! the unstable `allocator_api` of the standard library.
+
+pub use allocator_api2::alloc::{AllocError, Allocator, Layout};
+
+#[cfg(feature = "alloc")]
+pub use allocator_api2::{boxed::Box, vec::Vec};
@@ -0,0 +1,178 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+
+use crate::allocator::{AllocError, Layout};
+use crate::core::Pool;
+use crate::ffi::*;
+
+/// Typed wrapper struct for an [`ngx_array_t`] with elements of type `T`.
+///
+/// The array storage is allocated from the pool and grows on [`NgxArray::push`]. The elements are
+/// never dropped, similarly to the other pool allocations.
+///
+/// Indexing and iteration are available through the [`Deref`] implementation to a slice.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#array>
+#[repr(transparent)]
+pub struct NgxArray<T>(ngx_array_t, PhantomData<T>);
+
+impl<T> NgxArray<T> {
+    /// Creates an empty array with room for `n` elements, allocating it from the pool.
+    ///
+    /// Returns `None` if the size of `n` elements overflows or the allocation fails.
+    #[allow(clippy::mut_from_ref)] // the array is a new allocation, not a part of the pool
+    pub fn create(pool: &Pool, n: usize) -> Option<&mut NgxArray<T>> {
+        let n = n.max(1);
+        // ngx_array_create() does not check the size for overflow
+        Layout::array::<T>(n).ok()?;
+
+        let array = unsafe { ngx_array_create(pool.as_ngx_pool(), n, mem::size_of::<T>()) };
+        if array.is_null() {
+            return None;
+        }
+        Some(unsafe { Self::from_ngx_array_mut(array) })
+    }
+
+    /// Initializes an `ngx_array_t` in place with room for `n` elements of type `T`, as
+    /// `ngx_array_init` does.
+    ///
+    /// Returns `None` if the size of `n` elements overflows or the allocation fails.
+    pub fn init<'a>(array: &'a mut ngx_array_t, pool: &Pool, n: usize) -> Option<&'a mut NgxArray<T>> {
+        let n = n.max(1);
+        let elts = pool.alloc(Layout::array::<T>(n).ok()?.size());
+        if elts.is_null() {
+            return None;
+        }
+
+        array.elts = elts;
+        array.nelts = 0;
+        array.size = mem::size_of::<T>();
+        array.nalloc = n;
+        array.pool = pool.as_ngx_pool();
+
+        Some(unsafe { Self::from_ngx_array_mut(array) })
+    }
+
+    /// Creates an `NgxArray` reference from an `ngx_array_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid initialized `ngx_array_t` pointer is provided, the
+    /// elements of which are of type `T`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_array<'a>(array: *const ngx_array_t) -> &'a NgxArray<T> {
+        assert!(!array.is_null());
+        debug_assert_eq!((*array).size, mem::size_of::<T>());
+        &*array.cast::<NgxArray<T>>()
+    }
+
+    /// Creates a mutable `NgxArray` reference from an `ngx_array_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid initialized `ngx_array_t` pointer is provided, the
+    /// elements of which are of type `T`, and that the array is not aliased for the lifetime `'a`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_array_mut<'a>(array: *mut ngx_array_t) -> &'a mut NgxArray<T> {
+        assert!(!array.is_null());
+        debug_assert_eq!((*array).size, mem::size_of::<T>());
+        &mut *array.cast::<NgxArray<T>>()
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_array_t`.
+    pub fn as_ngx_array(&self) -> *const ngx_array_t {
+        &self.0
+    }
+
+    /// Appends an element to the array, growing the storage if necessary.
+    ///
+    /// Returns a reference to the added element.
+    pub fn push(&mut self, value: T) -> Result<&mut T, AllocError> {
+        let p = unsafe { ngx_array_push(&mut self.0) }.cast::<T>();
+        if p.is_null() {
+            return Err(AllocError);
+        }
+
+        unsafe {
+            ptr::write(p, value);
+            Ok(&mut *p)
+        }
+    }
+
+    /// Returns the number of elements in the array.
+    pub fn len(&self) -> usize {
+        self.0.nelts
+    }
+
+    /// Returns `true` if the array contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.0.nelts == 0
+    }
+
+    /// Returns the elements as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        if self.0.nelts == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.0.elts.cast(), self.0.nelts) }
+    }
+
+    /// Returns the elements as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        if self.0.nelts == 0 {
+            return &mut [];
+        }
+        unsafe { slice::from_raw_parts_mut(self.0.elts.cast(), self.0.nelts) }
+    }
+}
+
+impl<T> Deref for NgxArray<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for NgxArray<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a NgxArray<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut NgxArray<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_mut_slice().iter_mut()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::core::pool::tests::TestPool;
+
+    #[test]
+    fn init_overflow() {
+        let pool = TestPool::new();
+        let mut array: ngx_array_t = unsafe { mem::zeroed() };
+
+        assert!(NgxArray::<u64>::init(&mut array, &pool, usize::MAX / 4).is_none());
+
+        let array = NgxArray::<u64>::init(&mut array, &pool, 0).unwrap();
+        assert!(array.is_empty());
+        assert_eq!(unsafe { (*array.as_ngx_array()).nalloc }, 1);
+    }
+}
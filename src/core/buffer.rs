@@ -138,3 +138,60 @@ impl Buffer for MemoryBuffer {
         self.0
     }
 }
+
+/// Wrapper struct for a file buffer, providing methods for working with an `ngx_buf_t` that references
+/// a range of an open file.
+///
+/// File buffers are sent with `sendfile()` or read with AIO when enabled in the configuration.
+pub struct FileBuffer(*mut ngx_buf_t);
+
+impl FileBuffer {
+    /// Creates a new `FileBuffer` from an `ngx_buf_t` pointer.
+    ///
+    /// # Panics
+    /// Panics if the given buffer pointer is null.
+    pub fn from_ngx_buf(buf: *mut ngx_buf_t) -> FileBuffer {
+        assert!(!buf.is_null());
+        FileBuffer(buf)
+    }
+
+    /// Sets the range of the file referenced by the buffer.
+    ///
+    /// # Panics
+    /// Panics if `start` is greater than `end`.
+    pub fn set_file_range(&mut self, start: off_t, end: off_t) {
+        assert!(start <= end);
+        unsafe {
+            (*self.0).file_pos = start;
+            (*self.0).file_last = end;
+            (*self.0).set_in_file(if end > start { 1 } else { 0 });
+        }
+    }
+}
+
+impl Buffer for FileBuffer {
+    /// Returns the underlying `ngx_buf_t` pointer as a raw pointer.
+    fn as_ngx_buf(&self) -> *const ngx_buf_t {
+        self.0
+    }
+
+    /// Returns a mutable reference to the underlying `ngx_buf_t` pointer.
+    fn as_ngx_buf_mut(&mut self) -> *mut ngx_buf_t {
+        self.0
+    }
+
+    /// Returns an empty slice, as the contents of a file buffer are not loaded into memory.
+    fn as_bytes(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Returns the length of the referenced file range.
+    fn len(&self) -> usize {
+        unsafe {
+            let pos = (*self.0).file_pos;
+            let last = (*self.0).file_last;
+            assert!(last >= pos);
+            (last - pos) as usize
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::{cmp, ptr};
+
+use crate::core::buffer::{Buffer, TemporaryBuffer};
+use crate::core::file::OpenFileInfo;
+use crate::core::pool::Pool;
+use crate::ffi::*;
+
+/// Returns `true` if the buffer contents are in memory.
+///
+/// Replaces the `ngx_buf_in_memory` C macro.
+#[inline]
+pub fn buf_in_memory(buf: &ngx_buf_t) -> bool {
+    buf.temporary() != 0 || buf.memory() != 0 || buf.mmap() != 0
+}
+
+/// Returns the size of the data referenced by the buffer, either in memory or in a file.
+///
+/// Replaces the `ngx_buf_size` C macro.
+#[inline]
+pub fn buf_size(buf: &ngx_buf_t) -> usize {
+    if buf_in_memory(buf) {
+        (buf.last as usize).wrapping_sub(buf.pos as usize)
+    } else {
+        (buf.file_last - buf.file_pos) as usize
+    }
+}
+
+/// A linked list of buffers ([`ngx_chain_t`]) allocated from a [`Pool`].
+///
+/// The chain is used to pass the response body to the output filters, see
+/// [`Request::output_filter`](crate::http::Request::output_filter).
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#buffer>
+pub struct Chain<'a> {
+    pool: &'a Pool,
+    head: *mut ngx_chain_t,
+    tail: *mut ngx_chain_t,
+}
+
+impl<'a> Chain<'a> {
+    /// Creates an empty chain allocating the links and buffers from `pool`.
+    pub fn new(pool: &'a Pool) -> Self {
+        Self {
+            pool,
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    /// Creates a chain from an existing list of [`ngx_chain_t`] links.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided either a null pointer or a valid chain, with all the links and
+    /// buffers allocated from `pool` or outliving it.
+    pub unsafe fn from_ngx_chain(pool: &'a Pool, head: *mut ngx_chain_t) -> Self {
+        let mut tail = head;
+        while !tail.is_null() && !(*tail).next.is_null() {
+            tail = (*tail).next;
+        }
+        Self { pool, head, tail }
+    }
+
+    /// Appends a buffer to the end of the chain.
+    ///
+    /// Returns `None` if the chain link allocation fails.
+    pub fn push<B: Buffer>(&mut self, mut buf: B) -> Option<()> {
+        self.push_ngx_buf(buf.as_ngx_buf_mut())
+    }
+
+    /// Appends a copy of `data` to the end of the chain.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn push_bytes(&mut self, data: &[u8]) -> Option<()> {
+        let mut buf = self.pool.create_buffer(data.len())?;
+        unsafe {
+            let b = buf.as_ngx_buf_mut();
+            ptr::copy_nonoverlapping(data.as_ptr(), (*b).pos, data.len());
+            (*b).last = (*b).pos.add(data.len());
+        }
+        self.push(buf)
+    }
+
+    /// Appends a buffer referencing the static `data` to the end of the chain, without copying.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn push_static(&mut self, data: &'static [u8]) -> Option<()> {
+        let buf = self.pool.create_buffer_from_static_bytes(data)?;
+        self.push(buf)
+    }
+
+    /// Appends a buffer referencing the whole contents of an open file to the end of the chain.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn push_file(&mut self, of: &OpenFileInfo, name: ngx_str_t, log: *mut ngx_log_t) -> Option<()> {
+        let buf = self.pool.create_file_buffer(of, name, log)?;
+        self.push(buf)
+    }
+
+    /// Appends a raw buffer to the end of the chain.
+    ///
+    /// Returns `None` if the chain link allocation fails.
+    pub fn push_ngx_buf(&mut self, buf: *mut ngx_buf_t) -> Option<()> {
+        let cl = unsafe { ngx_alloc_chain_link(self.pool.as_ngx_pool()) };
+        if cl.is_null() {
+            return None;
+        }
+
+        unsafe {
+            (*cl).buf = buf;
+            (*cl).next = ptr::null_mut();
+
+            if self.tail.is_null() {
+                self.head = cl;
+            } else {
+                (*self.tail).next = cl;
+            }
+        }
+        self.tail = cl;
+
+        Some(())
+    }
+
+    /// Marks the end of the output.
+    ///
+    /// Sets the `last_in_chain` flag and, if `last` is `true`, the `last_buf` flag on the last buffer
+    /// of the chain. `last` should be set only for the main request, see
+    /// [`Request::is_main`](crate::http::Request::is_main).
+    /// An empty buffer is added if the chain is empty.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn set_last_buf(&mut self, last: bool) -> Option<()> {
+        let buf = self.last_or_empty_buf()?;
+        unsafe {
+            (*buf).set_last_buf(if last { 1 } else { 0 });
+            (*buf).set_last_in_chain(1);
+        }
+        Some(())
+    }
+
+    /// Requests the output filters to send all the buffered data.
+    ///
+    /// Sets the `flush` flag on the last buffer of the chain. An empty buffer is added if the
+    /// chain is empty.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn set_flush(&mut self) -> Option<()> {
+        let buf = self.last_or_empty_buf()?;
+        unsafe { (*buf).set_flush(1) };
+        Some(())
+    }
+
+    /// Returns `true` if the chain has no buffers.
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// Returns the total size of the data referenced by all buffers of the chain.
+    pub fn total_len(&self) -> usize {
+        self.iter().map(buf_size).sum()
+    }
+
+    /// Returns an iterator over the buffers of the chain.
+    pub fn iter(&self) -> ChainIter<'_> {
+        ChainIter {
+            cl: self.head,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a raw pointer to the first link of the chain, or a null pointer if the chain is empty.
+    pub fn as_ngx_chain(&self) -> *mut ngx_chain_t {
+        self.head
+    }
+
+    fn last_or_empty_buf(&mut self) -> Option<*mut ngx_buf_t> {
+        if self.tail.is_null() {
+            let buf = self.pool.calloc_type::<ngx_buf_t>();
+            if buf.is_null() {
+                return None;
+            }
+            self.push_ngx_buf(buf)?;
+        }
+        Some(unsafe { (*self.tail).buf })
+    }
+}
+
+impl<'a, 'c> From<&'c mut Chain<'a>> for *mut ngx_chain_t {
+    fn from(chain: &'c mut Chain<'a>) -> Self {
+        chain.head
+    }
+}
+
+/// Iterator over the buffers of a [`Chain`].
+pub struct ChainIter<'c> {
+    cl: *mut ngx_chain_t,
+    _p: PhantomData<&'c ngx_chain_t>,
+}
+
+impl<'c> Iterator for ChainIter<'c> {
+    type Item = &'c ngx_buf_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cl.is_null() {
+            return None;
+        }
+        // SAFETY: the links and buffers of the chain are valid for the lifetime of the pool
+        unsafe {
+            let buf = &*(*self.cl).buf;
+            self.cl = (*self.cl).next;
+            Some(buf)
+        }
+    }
+}
+
+/// Writer appending formatted output to a [`Chain`].
+///
+/// The data is copied into temporary buffers of a fixed size allocated from the pool; a new buffer is
+/// added to the chain when the current one is full.
+pub struct ChainWriter<'a> {
+    chain: Chain<'a>,
+    current: *mut ngx_buf_t,
+    buf_size: usize,
+}
+
+impl<'a> ChainWriter<'a> {
+    /// Creates a writer allocating buffers of `buf_size` bytes from `pool`.
+    ///
+    /// # Panics
+    /// Panics if `buf_size` is zero.
+    pub fn new(pool: &'a Pool, buf_size: usize) -> Self {
+        assert!(buf_size > 0);
+        Self {
+            chain: Chain::new(pool),
+            current: ptr::null_mut(),
+            buf_size,
+        }
+    }
+
+    /// Appends a copy of `data` to the chain.
+    ///
+    /// Returns `None` if the buffer allocation fails.
+    pub fn write_bytes(&mut self, mut data: &[u8]) -> Option<()> {
+        while !data.is_empty() {
+            if self.current.is_null() || unsafe { (*self.current).last == (*self.current).end } {
+                let mut buf = self.chain.pool.create_buffer(self.buf_size)?;
+                self.current = buf.as_ngx_buf_mut();
+                self.chain.push(buf)?;
+            }
+
+            unsafe {
+                let b = &mut *self.current;
+                let n = cmp::min(data.len(), (b.end as usize) - (b.last as usize));
+                ptr::copy_nonoverlapping(data.as_ptr(), b.last, n);
+                b.last = b.last.add(n);
+                data = &data[n..];
+            }
+        }
+        Some(())
+    }
+
+    /// Returns a mutable reference to the chain being written.
+    pub fn chain_mut(&mut self) -> &mut Chain<'a> {
+        &mut self.chain
+    }
+
+    /// Consumes the writer, returning the written chain.
+    pub fn into_chain(self) -> Chain<'a> {
+        self.chain
+    }
+}
+
+impl fmt::Write for ChainWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).ok_or(fmt::Error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for ChainWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_bytes(buf).ok_or(std::io::ErrorKind::OutOfMemory)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Free and busy buffer lists of a streaming filter.
+///
+/// Body filters producing their own buffers should reuse the buffers already sent to the client
+/// instead of allocating new ones for each portion of the response. The recycler keeps the buffers
+/// still held by the next filters in the busy list and moves them to the free list once they are sent,
+/// limiting the number of allocated buffers to a fixed budget, just like the `gzip_buffers` and
+/// `sub_filter` do.
+///
+/// The recycler is expected to be stored in the module context of the request.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#http_body_buffers_reuse>
+pub struct BufferRecycler {
+    free: *mut ngx_chain_t,
+    busy: *mut ngx_chain_t,
+    tag: ngx_buf_tag_t,
+    size: usize,
+    num: usize,
+    allocated: usize,
+}
+
+impl BufferRecycler {
+    /// Creates a recycler allocating up to `num` buffers of `size` bytes.
+    ///
+    /// The `tag` identifies the buffers owned by the recycler and is usually the address of the module,
+    /// e.g. `addr_of!(ngx_http_foo_module) as ngx_buf_tag_t`.
+    pub fn new(tag: ngx_buf_tag_t, size: usize, num: usize) -> Self {
+        Self {
+            free: ptr::null_mut(),
+            busy: ptr::null_mut(),
+            tag,
+            size,
+            num,
+            allocated: 0,
+        }
+    }
+
+    /// Returns an empty buffer, either reused from the free list or allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails or all the buffers are in use, see
+    /// [`BufferRecycler::is_exhausted`].
+    pub fn get_buf(&mut self, pool: &Pool) -> Option<TemporaryBuffer> {
+        if self.is_exhausted() {
+            return None;
+        }
+
+        let p = pool.as_ngx_pool();
+        let cl = unsafe { ngx_chain_get_free_buf(p, &mut self.free) };
+        if cl.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let b = (*cl).buf;
+
+            // return the link to the pool, the buffer will be linked into the output chain
+            (*cl).next = (*p).chain;
+            (*p).chain = cl;
+
+            if (*b).start.is_null() {
+                let start = ngx_palloc(p, self.size) as *mut u8;
+                if start.is_null() {
+                    return None;
+                }
+
+                (*b).start = start;
+                (*b).pos = start;
+                (*b).last = start;
+                (*b).end = start.add(self.size);
+                (*b).tag = self.tag;
+                (*b).set_temporary(1);
+                (*b).set_recycled(1);
+
+                self.allocated += 1;
+            }
+
+            Some(TemporaryBuffer::from_ngx_buf(b))
+        }
+    }
+
+    /// Updates the lists after passing `out` to the next filter.
+    ///
+    /// The sent buffers with the recycler tag are moved to the free list, the buffers still in use
+    /// are kept in the busy list.
+    pub fn update(&mut self, pool: &Pool, out: *mut ngx_chain_t) {
+        let mut out = out;
+        unsafe {
+            ngx_chain_update_chains(pool.as_ngx_pool(), &mut self.free, &mut self.busy, &mut out, self.tag);
+        }
+    }
+
+    /// Returns `true` if any of the buffers is still held by the next filters.
+    ///
+    /// Filters should set the corresponding `buffered` flag of the connection while the buffers are
+    /// busy.
+    pub fn is_busy(&self) -> bool {
+        !self.busy.is_null()
+    }
+
+    /// Returns `true` if no buffers can be obtained until the busy ones are sent.
+    pub fn is_exhausted(&self) -> bool {
+        self.free.is_null() && self.allocated >= self.num
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::core::pool::tests::TestPool;
+
+    // the buffer functions, as implemented in src/core/ngx_buf.c
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_alloc_chain_link(pool: *mut ngx_pool_t) -> *mut ngx_chain_t {
+        let cl = (*pool).chain;
+        if !cl.is_null() {
+            (*pool).chain = (*cl).next;
+            return cl;
+        }
+        ngx_palloc(pool, core::mem::size_of::<ngx_chain_t>()).cast()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_create_temp_buf(pool: *mut ngx_pool_t, size: usize) -> *mut ngx_buf_t {
+        let b = ngx_pcalloc(pool, core::mem::size_of::<ngx_buf_t>()).cast::<ngx_buf_t>();
+        let start = ngx_palloc(pool, size).cast::<u8>();
+        if b.is_null() || start.is_null() {
+            return ptr::null_mut();
+        }
+
+        (*b).start = start;
+        (*b).pos = start;
+        (*b).last = start;
+        (*b).end = start.add(size);
+        (*b).set_temporary(1);
+        b
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_chain_get_free_buf(
+        pool: *mut ngx_pool_t,
+        free: *mut *mut ngx_chain_t,
+    ) -> *mut ngx_chain_t {
+        if !(*free).is_null() {
+            let cl = *free;
+            *free = (*cl).next;
+            (*cl).next = ptr::null_mut();
+            return cl;
+        }
+
+        let cl = ngx_alloc_chain_link(pool);
+        if cl.is_null() {
+            return ptr::null_mut();
+        }
+        (*cl).buf = ngx_pcalloc(pool, core::mem::size_of::<ngx_buf_t>()).cast();
+        if (*cl).buf.is_null() {
+            return ptr::null_mut();
+        }
+        (*cl).next = ptr::null_mut();
+        cl
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_chain_update_chains(
+        pool: *mut ngx_pool_t,
+        free: *mut *mut ngx_chain_t,
+        busy: *mut *mut ngx_chain_t,
+        out: *mut *mut ngx_chain_t,
+        tag: ngx_buf_tag_t,
+    ) {
+        if !(*out).is_null() {
+            if (*busy).is_null() {
+                *busy = *out;
+            } else {
+                let mut cl = *busy;
+                while !(*cl).next.is_null() {
+                    cl = (*cl).next;
+                }
+                (*cl).next = *out;
+            }
+            *out = ptr::null_mut();
+        }
+
+        while !(*busy).is_null() {
+            let cl = *busy;
+
+            if (*(*cl).buf).tag != tag {
+                *busy = (*cl).next;
+                (*cl).next = (*pool).chain;
+                (*pool).chain = cl;
+                continue;
+            }
+
+            if buf_size(&*(*cl).buf) != 0 {
+                break;
+            }
+
+            (*(*cl).buf).pos = (*(*cl).buf).start;
+            (*(*cl).buf).last = (*(*cl).buf).start;
+
+            *busy = (*cl).next;
+            (*cl).next = *free;
+            *free = cl;
+        }
+    }
+
+    /// Fills the buffer with `n` bytes.
+    fn fill(buf: &mut TemporaryBuffer, n: usize) -> *mut ngx_buf_t {
+        let b = buf.as_ngx_buf_mut();
+        unsafe { (*b).last = (*b).pos.add(n) };
+        b
+    }
+
+    /// Marks the buffer as sent by the next filters.
+    fn consume(b: *mut ngx_buf_t) {
+        unsafe { (*b).pos = (*b).last };
+    }
+
+    #[test]
+    fn recycler() {
+        let pool = TestPool::new();
+        let tag = 1usize as ngx_buf_tag_t;
+        let mut recycler = BufferRecycler::new(tag, 16, 2);
+
+        assert!(!recycler.is_busy());
+        assert!(!recycler.is_exhausted());
+
+        let mut b1 = recycler.get_buf(&pool).unwrap();
+        let mut b2 = recycler.get_buf(&pool).unwrap();
+        assert!(recycler.is_exhausted());
+        assert!(recycler.get_buf(&pool).is_none());
+
+        let b1 = fill(&mut b1, 4);
+        let b2 = fill(&mut b2, 8);
+        unsafe {
+            assert_eq!((*b1).end.offset_from((*b1).start), 16);
+            assert_eq!((*b1).tag, tag);
+            assert!((*b1).recycled() != 0);
+        }
+
+        let mut out = Chain::new(&pool);
+        out.push_ngx_buf(b1).unwrap();
+        out.push_ngx_buf(b2).unwrap();
+        recycler.update(&pool, out.as_ngx_chain());
+        assert!(recycler.is_busy());
+        assert!(recycler.is_exhausted());
+
+        // the buffers are released in order
+        consume(b2);
+        recycler.update(&pool, ptr::null_mut());
+        assert!(recycler.is_busy());
+        assert!(recycler.is_exhausted());
+
+        consume(b1);
+        recycler.update(&pool, ptr::null_mut());
+        assert!(!recycler.is_busy());
+        assert!(!recycler.is_exhausted());
+
+        // the free buffers are reused and reset
+        let mut b3 = recycler.get_buf(&pool).unwrap();
+        let mut b4 = recycler.get_buf(&pool).unwrap();
+        let (b3, b4) = (b3.as_ngx_buf_mut(), b4.as_ngx_buf_mut());
+        assert!((b3 == b1 && b4 == b2) || (b3 == b2 && b4 == b1));
+        unsafe {
+            assert_eq!((*b3).pos, (*b3).start);
+            assert_eq!((*b3).last, (*b3).start);
+        }
+        assert!(recycler.is_exhausted());
+        assert!(recycler.get_buf(&pool).is_none());
+    }
+
+    #[test]
+    fn recycler_foreign() {
+        let pool = TestPool::new();
+        let mut recycler = BufferRecycler::new(1usize as ngx_buf_tag_t, 16, 1);
+
+        // the buffers of other modules are not tracked
+        let mut out = Chain::new(&pool);
+        out.push_bytes(b"foreign").unwrap();
+        recycler.update(&pool, out.as_ngx_chain());
+        assert!(!recycler.is_busy());
+        assert!(!recycler.is_exhausted());
+    }
+
+    fn contents(chain: &Chain) -> std::vec::Vec<u8> {
+        let mut out = std::vec::Vec::new();
+        for b in chain.iter() {
+            out.extend_from_slice(unsafe { core::slice::from_raw_parts(b.pos, buf_size(b)) });
+        }
+        out
+    }
+
+    #[test]
+    fn writer() {
+        use core::fmt::Write;
+
+        let pool = TestPool::new();
+        let mut w = ChainWriter::new(&pool, 4);
+
+        w.write_bytes(b"hello").unwrap();
+        let name = "world";
+        write!(w, ", {name}").unwrap();
+        std::io::Write::write_all(&mut w, b"!").unwrap();
+
+        let chain = w.into_chain();
+        assert_eq!(chain.iter().count(), 4);
+        assert_eq!(chain.total_len(), 13);
+        assert_eq!(contents(&chain), b"hello, world!");
+
+        // the buffers are filled before allocating the next one
+        assert!(chain.iter().take(3).all(|b| b.last == b.end));
+    }
+
+    #[test]
+    fn writer_last_buf() {
+        let pool = TestPool::new();
+        let mut w = ChainWriter::new(&pool, 16);
+        assert!(w.chain_mut().is_empty());
+
+        std::io::Write::write_all(&mut w, b"data").unwrap();
+        w.chain_mut().set_last_buf(true).unwrap();
+
+        let chain = w.into_chain();
+        assert_eq!(chain.iter().count(), 1);
+        assert_eq!(contents(&chain), b"data");
+
+        let b = chain.iter().last().unwrap();
+        assert!(b.last_buf() != 0 && b.last_in_chain() != 0);
+    }
+}
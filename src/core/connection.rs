@@ -0,0 +1,332 @@
+use core::fmt;
+use core::mem;
+use core::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use core::ptr;
+
+use crate::core::{NgxStr, Pool, Status};
+use crate::ffi::*;
+
+/// Wrapper struct for an [`ngx_connection_t`] pointer, providing methods for working with client and upstream
+/// connections.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#connection>
+#[repr(transparent)]
+pub struct Connection(ngx_connection_t);
+
+impl<'a> From<&'a Connection> for *const ngx_connection_t {
+    fn from(connection: &'a Connection) -> Self {
+        &connection.0 as *const _
+    }
+}
+
+impl<'a> From<&'a mut Connection> for *mut ngx_connection_t {
+    fn from(connection: &'a mut Connection) -> Self {
+        &mut connection.0 as *mut _
+    }
+}
+
+impl Connection {
+    /// Create a [`Connection`] from an [`ngx_connection_t`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_connection_t`
+    /// which shares the same representation as `Connection`.
+    pub unsafe fn from_ngx_connection<'a>(c: *mut ngx_connection_t) -> &'a mut Connection {
+        &mut *c.cast::<Connection>()
+    }
+
+    /// Socket descriptor of the connection.
+    pub fn fd(&self) -> ngx_socket_t {
+        self.0.fd
+    }
+
+    /// Unique number of the connection, as used in the log messages.
+    pub fn number(&self) -> ngx_atomic_uint_t {
+        self.0.number
+    }
+
+    /// Number of requests processed on this connection, including the current one.
+    pub fn requests(&self) -> ngx_uint_t {
+        self.0.requests
+    }
+
+    /// Number of bytes sent on this connection.
+    pub fn sent(&self) -> off_t {
+        self.0.sent
+    }
+
+    /// Connection pool.
+    pub fn pool(&self) -> &Pool {
+        // SAFETY: an active connection always has a valid pool
+        unsafe { Pool::from_ngx_pool(self.0.pool) }
+    }
+
+    /// Pointer to a [`ngx_log_t`] of the connection.
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        self.0.log
+    }
+
+    /// Textual representation of the peer address, as logged by nginx.
+    pub fn addr_text(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.addr_text) }
+    }
+
+    /// Address of the peer (the client for the client connections).
+    ///
+    /// Returns `None` for the address families not representable by [`SocketAddr`], e.g. `AF_UNIX`.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        unsafe { sockaddr_to_socket_addr(self.0.sockaddr, self.0.socklen) }
+    }
+
+    /// Local address the connection was accepted on.
+    ///
+    /// For the connections accepted on a wildcard listen socket the address is resolved with
+    /// `getsockname()` and cached in the connection on the first call.
+    pub fn local_addr(&mut self) -> Option<SocketAddr> {
+        if self.0.local_sockaddr.is_null() {
+            // SAFETY: with a null `s` argument the function only resolves and caches the address
+            let rc = unsafe { ngx_connection_local_sockaddr(&mut self.0, ptr::null_mut(), 0) };
+            if rc != Status::NGX_OK.into() {
+                return None;
+            }
+        }
+        unsafe { sockaddr_to_socket_addr(self.0.local_sockaddr, self.0.local_socklen) }
+    }
+
+    /// Pending output flags of the connection.
+    ///
+    /// A non-zero value means that some of the output filters or the low-level send functions
+    /// hold data not yet written to the socket. See `NGX_HTTP_*_BUFFERED` and
+    /// `NGX_LOWLEVEL_BUFFERED` for the meaning of the individual bits.
+    pub fn buffered(&self) -> u8 {
+        self.0.buffered() as u8
+    }
+
+    /// Returns `true` if the connection has buffered output.
+    pub fn is_buffered(&self) -> bool {
+        self.buffered() != 0
+    }
+
+    /// Returns `true` if an I/O operation on the connection has timed out.
+    pub fn timedout(&self) -> bool {
+        self.0.timedout() != 0
+    }
+
+    /// Returns `true` if the connection has encountered an error.
+    pub fn error(&self) -> bool {
+        self.0.error() != 0
+    }
+
+    /// SSL state of the connection.
+    ///
+    /// Returns `None` for plain text connections.
+    #[cfg(ngx_feature = "ssl")]
+    pub fn ssl(&self) -> Option<SslConnection<'_>> {
+        if self.0.ssl.is_null() {
+            return None;
+        }
+        Some(SslConnection(self))
+    }
+
+    /// Returns the inner data structure that the Connection object is wrapping.
+    pub fn get_inner(&self) -> &ngx_connection_t {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("number", &self.0.number)
+            .field("fd", &self.0.fd)
+            .field("addr_text", &self.0.addr_text)
+            .finish()
+    }
+}
+
+/// SSL state of a [`Connection`].
+///
+/// Wraps the `ngx_ssl_get_*` family of functions used by the `$ssl_*` variables. Values that
+/// require memory allocation are allocated from the connection pool.
+#[cfg(ngx_feature = "ssl")]
+pub struct SslConnection<'a>(&'a Connection);
+
+#[cfg(ngx_feature = "ssl")]
+impl<'a> SslConnection<'a> {
+    /// Returns `true` if the SSL handshake is complete.
+    pub fn handshaked(&self) -> bool {
+        self.get_inner().handshaked() != 0
+    }
+
+    /// Protocol of the established SSL connection, e.g. `TLSv1.3`.
+    pub fn protocol(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_protocol)
+    }
+
+    /// Name of the cipher used for the established SSL connection.
+    pub fn cipher(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_cipher_name)
+    }
+
+    /// Server name requested through SNI.
+    pub fn server_name(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_server_name)
+    }
+
+    /// Returns `true` if the SSL session was reused.
+    pub fn session_reused(&self) -> bool {
+        self.get(ngx_ssl_get_session_reused)
+            .is_some_and(|s| s.as_bytes() == b"r")
+    }
+
+    /// Result of the client certificate verification: `SUCCESS`, `FAILED:reason` or `NONE`.
+    pub fn client_verify(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_client_verify)
+    }
+
+    /// Client certificate in the PEM format.
+    ///
+    /// Returns `None` if the client did not present a certificate.
+    pub fn client_certificate(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_raw_certificate)
+    }
+
+    /// Subject DN of the client certificate.
+    ///
+    /// Returns `None` if the client did not present a certificate.
+    pub fn client_subject_dn(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_subject_dn)
+    }
+
+    /// Issuer DN of the client certificate.
+    ///
+    /// Returns `None` if the client did not present a certificate.
+    pub fn client_issuer_dn(&self) -> Option<&'a NgxStr> {
+        self.get(ngx_ssl_get_issuer_dn)
+    }
+
+    /// Returns the inner data structure that the SslConnection object is wrapping.
+    pub fn get_inner(&self) -> &'a ngx_ssl_connection_t {
+        // SAFETY: `SslConnection` is only created for connections with non-null `ssl`
+        unsafe { &*self.0 .0.ssl }
+    }
+
+    fn get(
+        &self,
+        getter: unsafe extern "C" fn(*mut ngx_connection_t, *mut ngx_pool_t, *mut ngx_str_t) -> ngx_int_t,
+    ) -> Option<&'a NgxStr> {
+        let c = self.0.get_inner() as *const _ as *mut ngx_connection_t;
+        let mut s = ngx_str_t::empty();
+        // SAFETY: the getters only read the SSL state of the connection and allocate the result
+        // from the connection pool when needed, thus the value lives as long as the connection.
+        unsafe {
+            if getter(c, (*c).pool, &mut s) != Status::NGX_OK.into() || s.is_empty() {
+                return None;
+            }
+            Some(NgxStr::from_ngx_str(s))
+        }
+    }
+}
+
+/// Converts a socket address stored in [`sockaddr`] to a [`SocketAddr`].
+///
+/// Returns `None` for a null pointer or the address families not representable by [`SocketAddr`].
+///
+/// # Safety
+///
+/// The caller has provided either a null pointer or a pointer to a valid socket address of at least
+/// `len` bytes.
+pub unsafe fn sockaddr_to_socket_addr(sa: *const sockaddr, len: socklen_t) -> Option<SocketAddr> {
+    if sa.is_null() {
+        return None;
+    }
+
+    match (*sa).sa_family as u32 {
+        AF_INET if len as usize >= mem::size_of::<sockaddr_in>() => {
+            let sin = &*sa.cast::<sockaddr_in>();
+            let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+            Some(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(sin.sin_port))))
+        }
+        AF_INET6 if len as usize >= mem::size_of::<sockaddr_in6>() => {
+            let sin6 = &*sa.cast::<sockaddr_in6>();
+            let octets: [u8; 16] = mem::transmute(sin6.sin6_addr);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(octets),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Stores a [`SocketAddr`] in a [`sockaddr_storage`].
+///
+/// Returns the length of the stored socket address.
+pub fn socket_addr_to_sockaddr(addr: &SocketAddr, storage: &mut sockaddr_storage) -> socklen_t {
+    // SAFETY: all-zero is a valid socket address representation
+    *storage = unsafe { mem::zeroed() };
+
+    match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe { &mut *ptr::from_mut(storage).cast::<sockaddr_in>() };
+            sin.sin_family = AF_INET as _;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+            mem::size_of::<sockaddr_in>() as socklen_t
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe { &mut *ptr::from_mut(storage).cast::<sockaddr_in6>() };
+            sin6.sin6_family = AF_INET6 as _;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_scope_id = addr.scope_id();
+            // SAFETY: in6_addr is a 16-byte network order address
+            sin6.sin6_addr = unsafe { mem::transmute::<[u8; 16], in6_addr>(addr.ip().octets()) };
+            mem::size_of::<sockaddr_in6>() as socklen_t
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(addr: SocketAddr) -> Option<SocketAddr> {
+        let mut storage: sockaddr_storage = unsafe { mem::zeroed() };
+        let len = socket_addr_to_sockaddr(&addr, &mut storage);
+        unsafe { sockaddr_to_socket_addr(ptr::addr_of!(storage).cast(), len) }
+    }
+
+    #[test]
+    fn sockaddr_v4() {
+        let addr = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 8080);
+        assert_eq!(round_trip(addr), Some(addr));
+    }
+
+    #[test]
+    fn sockaddr_v6() {
+        let ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let addr = SocketAddr::V6(SocketAddrV6::new(ip, 443, 0x12345, 3));
+        assert_eq!(round_trip(addr), Some(addr));
+    }
+
+    #[test]
+    fn sockaddr_other() {
+        let mut storage: sockaddr_storage = unsafe { mem::zeroed() };
+        storage.ss_family = AF_UNIX as _;
+        let len = mem::size_of_val(&storage) as socklen_t;
+        assert!(unsafe { sockaddr_to_socket_addr(ptr::addr_of!(storage).cast(), len) }.is_none());
+
+        // truncated address
+        let mut storage: sockaddr_storage = unsafe { mem::zeroed() };
+        let len = socket_addr_to_sockaddr(&"192.0.2.1:80".parse().unwrap(), &mut storage);
+        assert!(unsafe { sockaddr_to_socket_addr(ptr::addr_of!(storage).cast(), len - 1) }.is_none());
+
+        assert!(unsafe { sockaddr_to_socket_addr(ptr::null(), 0) }.is_none());
+    }
+}
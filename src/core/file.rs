@@ -0,0 +1,140 @@
+use core::mem;
+
+use crate::core::{Pool, Status};
+use crate::ffi::*;
+
+/// Wrapper struct for an [`ngx_open_file_info_t`], providing methods for working with files opened through the
+/// open file cache.
+///
+/// The structure holds both the parameters of the cache lookup and the information about the opened file.
+///
+/// See <https://nginx.org/en/docs/http/ngx_http_core_module.html#open_file_cache>
+#[repr(transparent)]
+pub struct OpenFileInfo(ngx_open_file_info_t);
+
+impl Default for OpenFileInfo {
+    fn default() -> Self {
+        // SAFETY: `ngx_open_file_info_t` is a plain C structure and nginx expects it to be zero-initialized
+        Self(unsafe { mem::zeroed() })
+    }
+}
+
+impl OpenFileInfo {
+    /// Sets the time after which the cached file information should be validated.
+    pub fn set_valid(&mut self, valid: time_t) -> &mut Self {
+        self.0.valid = valid;
+        self
+    }
+
+    /// Sets the minimum number of file accesses required to keep the descriptor open in the cache.
+    pub fn set_min_uses(&mut self, min_uses: ngx_uint_t) -> &mut Self {
+        self.0.min_uses = min_uses;
+        self
+    }
+
+    /// Enables caching of the file lookup errors.
+    pub fn set_errors(&mut self, errors: bool) -> &mut Self {
+        self.0.set_errors(errors as _);
+        self
+    }
+
+    /// Enables the file change notifications for the cached descriptor.
+    pub fn set_events(&mut self, events: bool) -> &mut Self {
+        self.0.set_events(events as _);
+        self
+    }
+
+    /// Sets the minimum file size for using the `O_DIRECT` flag.
+    pub fn set_directio(&mut self, directio: off_t) -> &mut Self {
+        self.0.directio = directio;
+        self
+    }
+
+    /// Sets the amount of read-ahead for the file.
+    pub fn set_read_ahead(&mut self, read_ahead: usize) -> &mut Self {
+        self.0.read_ahead = read_ahead;
+        self
+    }
+
+    /// File descriptor of the opened file.
+    pub fn fd(&self) -> ngx_fd_t {
+        self.0.fd
+    }
+
+    /// Unique identifier of the file, e.g. the inode number.
+    pub fn uniq(&self) -> ngx_file_uniq_t {
+        self.0.uniq
+    }
+
+    /// Modification time of the file.
+    pub fn mtime(&self) -> time_t {
+        self.0.mtime
+    }
+
+    /// Size of the file.
+    pub fn size(&self) -> off_t {
+        self.0.size
+    }
+
+    /// Returns `true` if the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.0.is_dir() != 0
+    }
+
+    /// Returns `true` if the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.0.is_file() != 0
+    }
+
+    /// Returns `true` if the file should be read with `O_DIRECT`.
+    pub fn is_directio(&self) -> bool {
+        self.0.is_directio() != 0
+    }
+
+    /// Error code of the failed file lookup.
+    pub fn err(&self) -> ngx_err_t {
+        self.0.err
+    }
+
+    /// Name of the system call that failed, e.g. `open()`.
+    pub fn failed(&self) -> Option<&core::ffi::CStr> {
+        if self.0.failed.is_null() {
+            return None;
+        }
+        // SAFETY: `failed` always points to a static C string
+        Some(unsafe { core::ffi::CStr::from_ptr(self.0.failed) })
+    }
+
+    /// Returns the inner data structure that the OpenFileInfo object is wrapping.
+    pub fn get_inner(&self) -> &ngx_open_file_info_t {
+        &self.0
+    }
+
+    /// Returns a mutable reference to the inner data structure that the OpenFileInfo object is wrapping.
+    pub fn get_inner_mut(&mut self) -> &mut ngx_open_file_info_t {
+        &mut self.0
+    }
+}
+
+/// Opens a file through the open file cache.
+///
+/// If `cache` is null, the file is opened directly. The descriptor is closed when the cache entry
+/// expires or, for the uncached files, with the `pool` cleanup.
+///
+/// Returns `Err` with the error code of the failed system call; see also [`OpenFileInfo::failed`].
+///
+/// # Safety
+///
+/// The caller has provided either a null pointer or a valid `ngx_open_file_cache_t` as `cache`.
+/// The `name` must be a null-terminated string that remains valid for the lifetime of the `pool`.
+pub unsafe fn open_cached_file(
+    cache: *mut ngx_open_file_cache_t,
+    name: &mut ngx_str_t,
+    of: &mut OpenFileInfo,
+    pool: &Pool,
+) -> Result<(), ngx_err_t> {
+    if ngx_open_cached_file(cache, name, &mut of.0, pool.as_ngx_pool()) != Status::NGX_OK.into() {
+        return Err(of.0.err);
+    }
+    Ok(())
+}
@@ -0,0 +1,602 @@
+use core::cmp::Ordering;
+use core::ffi::{c_void, CStr};
+use core::fmt;
+use core::marker::PhantomData;
+use core::{mem, ptr, slice};
+
+use crate::core::{Pool, Status};
+use crate::ffi::*;
+
+/// HashError - key cannot be added or the hash cannot be built.
+#[derive(Debug)]
+pub enum HashError {
+    /// Memory allocation failed
+    Alloc,
+    /// Key is already added or conflicts with another wildcard key
+    Duplicate,
+    /// Wildcard key is invalid
+    InvalidWildcard,
+    /// Hash cannot be built with the specified `max_size` and `bucket_size`
+    Build,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HashError {}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashError::Alloc => "memory allocation failed".fmt(fmt),
+            HashError::Duplicate => "duplicate key".fmt(fmt),
+            HashError::InvalidWildcard => "invalid wildcard".fmt(fmt),
+            HashError::Build => "could not build hash".fmt(fmt),
+        }
+    }
+}
+
+/// Builder for a [`Hash`], collecting the keys into an [`ngx_hash_keys_arrays_t`].
+///
+/// The hash and the values are referenced from the `pool` allocations for the lifetime `'a`, while
+/// the `temp_pool` is only used while building.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#hash>
+pub struct HashBuilder<'a, 't, T> {
+    keys: ngx_hash_keys_arrays_t,
+    pool: &'a Pool,
+    temp_pool: &'t Pool,
+    _p: PhantomData<&'a T>,
+}
+
+impl<'a, 't, T> HashBuilder<'a, 't, T> {
+    /// Creates a builder for a small number of keys.
+    pub fn new(pool: &'a Pool, temp_pool: &'t Pool) -> Result<Self, HashError> {
+        Self::with_type(pool, temp_pool, NGX_HASH_SMALL as _)
+    }
+
+    /// Creates a builder for a large number of keys, e.g. thousands of server names.
+    pub fn new_large(pool: &'a Pool, temp_pool: &'t Pool) -> Result<Self, HashError> {
+        Self::with_type(pool, temp_pool, NGX_HASH_LARGE as _)
+    }
+
+    fn with_type(pool: &'a Pool, temp_pool: &'t Pool, type_: ngx_uint_t) -> Result<Self, HashError> {
+        let mut keys: ngx_hash_keys_arrays_t = unsafe { mem::zeroed() };
+        keys.pool = pool.as_ngx_pool();
+        keys.temp_pool = temp_pool.as_ngx_pool();
+
+        if unsafe { ngx_hash_keys_array_init(&mut keys, type_) } != Status::NGX_OK.into() {
+            return Err(HashError::Alloc);
+        }
+
+        Ok(Self {
+            keys,
+            pool,
+            temp_pool,
+            _p: PhantomData,
+        })
+    }
+
+    /// Adds an exact key. The key is case-insensitive.
+    pub fn add(&mut self, key: &[u8], value: &'a T) -> Result<(), HashError> {
+        self.add_key(key, value, 0)
+    }
+
+    /// Adds a host name key, which can be an exact name or a wildcard name with the same semantics as
+    /// the `server_name` directive: `*.example.com`, `.example.com` or `www.example.*`.
+    /// The key is case-insensitive.
+    pub fn add_host(&mut self, key: &[u8], value: &'a T) -> Result<(), HashError> {
+        self.add_key(key, value, NGX_HASH_WILDCARD_KEY as _)
+    }
+
+    fn add_key(&mut self, key: &[u8], value: &'a T, flags: ngx_uint_t) -> Result<(), HashError> {
+        // ngx_hash_add_key() keeps the reference to the key and converts it to lowercase in place
+        let data = self.pool.alloc_unaligned(key.len()).cast::<u8>();
+        if data.is_null() {
+            return Err(HashError::Alloc);
+        }
+
+        let mut name = unsafe {
+            ptr::copy_nonoverlapping(key.as_ptr(), data, key.len());
+            ngx_str_t { len: key.len(), data }
+        };
+
+        let value = ptr::from_ref(value).cast_mut().cast::<c_void>();
+
+        match Status(unsafe { ngx_hash_add_key(&mut self.keys, &mut name, value, flags) }) {
+            Status::NGX_OK => Ok(()),
+            Status::NGX_BUSY => Err(HashError::Duplicate),
+            Status::NGX_DECLINED => Err(HashError::InvalidWildcard),
+            _ => Err(HashError::Alloc),
+        }
+    }
+
+    /// Builds the hash from the added keys.
+    ///
+    /// The `name` is used in the error messages; `max_size` and `bucket_size` have the same meaning
+    /// as for the `*_hash_max_size` and `*_hash_bucket_size` directives.
+    pub fn build(mut self, name: &'static CStr, max_size: usize, bucket_size: usize) -> Result<Hash<'a, T>, HashError> {
+        let mut combined: ngx_hash_combined_t = unsafe { mem::zeroed() };
+
+        let mut hinit: ngx_hash_init_t = unsafe { mem::zeroed() };
+        hinit.key = Some(ngx_hash_key_lc);
+        hinit.max_size = max_size;
+        hinit.bucket_size = bucket_size;
+        hinit.name = name.as_ptr().cast_mut();
+        hinit.pool = self.pool.as_ngx_pool();
+
+        if self.keys.keys.nelts != 0 {
+            hinit.hash = &mut combined.hash;
+            hinit.temp_pool = ptr::null_mut();
+
+            let rc = unsafe { ngx_hash_init(&mut hinit, self.keys.keys.elts.cast(), self.keys.keys.nelts) };
+            if rc != Status::NGX_OK.into() {
+                return Err(HashError::Build);
+            }
+        }
+
+        if self.keys.dns_wc_head.nelts != 0 {
+            combined.wc_head = self.build_wildcard(&mut hinit, HashKeys::Head)?;
+        }
+
+        if self.keys.dns_wc_tail.nelts != 0 {
+            combined.wc_tail = self.build_wildcard(&mut hinit, HashKeys::Tail)?;
+        }
+
+        Ok(Hash {
+            combined,
+            _p: PhantomData,
+        })
+    }
+
+    fn build_wildcard(
+        &mut self,
+        hinit: &mut ngx_hash_init_t,
+        which: HashKeys,
+    ) -> Result<*mut ngx_hash_wildcard_t, HashError> {
+        let array = match which {
+            HashKeys::Head => &mut self.keys.dns_wc_head,
+            HashKeys::Tail => &mut self.keys.dns_wc_tail,
+        };
+
+        let keys = unsafe { slice::from_raw_parts_mut(array.elts.cast::<ngx_hash_key_t>(), array.nelts) };
+        keys.sort_unstable_by(|a, b| dns_cmp(&a.key, &b.key));
+
+        hinit.hash = ptr::null_mut();
+        hinit.temp_pool = self.temp_pool.as_ngx_pool();
+
+        let rc = unsafe { ngx_hash_wildcard_init(hinit, keys.as_mut_ptr(), keys.len()) };
+        if rc != Status::NGX_OK.into() {
+            return Err(HashError::Build);
+        }
+
+        Ok(hinit.hash.cast())
+    }
+}
+
+enum HashKeys {
+    Head,
+    Tail,
+}
+
+/// Read-only hash with the exact and wildcard keys, wrapping an [`ngx_hash_combined_t`].
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#hash>
+pub struct Hash<'a, T> {
+    combined: ngx_hash_combined_t,
+    _p: PhantomData<&'a T>,
+}
+
+impl<'a, T> Hash<'a, T> {
+    /// Finds the value for the name, trying the exact keys first, then the wildcard keys starting
+    /// with `*` and then the wildcard keys ending with `*`.
+    ///
+    /// The name must be in lowercase, e.g. converted with `ngx_strlow`.
+    pub fn find(&self, name: &[u8]) -> Option<&'a T> {
+        let data = name.as_ptr().cast_mut();
+
+        let value = unsafe {
+            let key = ngx_hash_key(data, name.len());
+            ngx_hash_find_combined(ptr::from_ref(&self.combined).cast_mut(), key, data, name.len())
+        };
+
+        unsafe { value.cast::<T>().as_ref() }
+    }
+
+    /// Returns `true` if the hash contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.combined.hash.buckets.is_null() && self.combined.wc_head.is_null() && self.combined.wc_tail.is_null()
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_hash_combined_t`.
+    pub fn as_ngx_hash_combined(&self) -> *const ngx_hash_combined_t {
+        &self.combined
+    }
+}
+
+impl<T> fmt::Debug for Hash<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hash")
+            .field("size", &self.combined.hash.size)
+            .field("wc_head", &!self.combined.wc_head.is_null())
+            .field("wc_tail", &!self.combined.wc_tail.is_null())
+            .finish()
+    }
+}
+
+/// Compares the wildcard keys in reverse domain order, see `ngx_dns_strcmp`.
+fn dns_cmp(a: &ngx_str_t, b: &ngx_str_t) -> Ordering {
+    unsafe { ngx_dns_strcmp(a.data.cast(), b.data.cast()) }.cmp(&0)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::core::pool::tests::TestPool;
+
+    // the hash functions, as implemented in src/core/ngx_hash.c, with a single bucket in the hashes
+    // of the added names
+
+    const NALLOC: usize = 16;
+
+    unsafe fn init<T>(a: *mut ngx_array_t, pool: *mut ngx_pool_t) -> ngx_int_t {
+        (*a).elts = ngx_palloc(pool, NALLOC * mem::size_of::<T>());
+        if (*a).elts.is_null() {
+            return Status::NGX_ERROR.into();
+        }
+        (*a).nelts = 0;
+        (*a).size = mem::size_of::<T>();
+        (*a).nalloc = NALLOC;
+        (*a).pool = pool;
+        Status::NGX_OK.into()
+    }
+
+    unsafe fn elts<'e, T>(a: *const ngx_array_t) -> &'e [T] {
+        slice::from_raw_parts((*a).elts.cast(), (*a).nelts)
+    }
+
+    unsafe fn push<T>(a: *mut ngx_array_t, elt: T) -> ngx_int_t {
+        if (*a).nelts == (*a).nalloc {
+            return Status::NGX_ERROR.into();
+        }
+        (*a).elts.cast::<T>().add((*a).nelts).write(elt);
+        (*a).nelts += 1;
+        Status::NGX_OK.into()
+    }
+
+    /// Adds the name to the hash of the added names, failing with `NGX_BUSY` if it is present.
+    unsafe fn add_name(names: *mut ngx_array_t, pool: *mut ngx_pool_t, name: &[u8]) -> ngx_int_t {
+        if elts::<ngx_str_t>(names).iter().any(|x| x.as_bytes() == name) {
+            return Status::NGX_BUSY.into();
+        }
+
+        let data = ngx_pnalloc(pool, name.len()).cast::<u8>();
+        ptr::copy_nonoverlapping(name.as_ptr(), data, name.len());
+        push(names, ngx_str_t { len: name.len(), data })
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_keys_array_init(ha: *mut ngx_hash_keys_arrays_t, type_: ngx_uint_t) -> ngx_int_t {
+        let ha = &mut *ha;
+        let pool = ha.temp_pool;
+
+        ha.hsize = if type_ == NGX_HASH_SMALL as ngx_uint_t {
+            107
+        } else {
+            10007
+        };
+
+        for a in [&mut ha.keys, &mut ha.dns_wc_head, &mut ha.dns_wc_tail] {
+            if init::<ngx_hash_key_t>(a, pool) != Status::NGX_OK.into() {
+                return Status::NGX_ERROR.into();
+            }
+        }
+
+        for a in [&mut ha.keys_hash, &mut ha.dns_wc_head_hash, &mut ha.dns_wc_tail_hash] {
+            *a = ngx_pcalloc(pool, mem::size_of::<ngx_array_t>()).cast();
+            if a.is_null() || init::<ngx_str_t>(*a, pool) != Status::NGX_OK.into() {
+                return Status::NGX_ERROR.into();
+            }
+        }
+
+        Status::NGX_OK.into()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_add_key(
+        ha: *mut ngx_hash_keys_arrays_t,
+        key: *mut ngx_str_t,
+        value: *mut c_void,
+        flags: ngx_uint_t,
+    ) -> ngx_int_t {
+        let ha = &mut *ha;
+        let data = slice::from_raw_parts_mut((*key).data, (*key).len);
+        let mut last = data.len();
+
+        let skip = 'wildcard: {
+            if flags & NGX_HASH_WILDCARD_KEY as ngx_uint_t == 0 {
+                break 'wildcard None;
+            }
+
+            let mut n = 0;
+            for (i, c) in data.iter().enumerate() {
+                if *c == b'*' {
+                    n += 1;
+                    if n > 1 {
+                        return Status::NGX_DECLINED.into();
+                    }
+                }
+                if *c == b'.' && data.get(i + 1) == Some(&b'.') || *c == 0 {
+                    return Status::NGX_DECLINED.into();
+                }
+            }
+
+            if data.len() > 1 && data[0] == b'.' {
+                break 'wildcard Some(1);
+            }
+
+            if data.len() > 2 && data.starts_with(b"*.") {
+                break 'wildcard Some(2);
+            }
+
+            if data.len() > 2 && data.ends_with(b".*") {
+                last -= 2;
+                break 'wildcard Some(0);
+            }
+
+            if n != 0 {
+                return Status::NGX_DECLINED.into();
+            }
+
+            None
+        };
+
+        let Some(skip) = skip else {
+            // exact name
+            data.make_ascii_lowercase();
+
+            let rc = add_name(ha.keys_hash, ha.temp_pool, data);
+            if rc != Status::NGX_OK.into() {
+                return rc;
+            }
+
+            let key_hash = ngx_hash_key(data.as_mut_ptr(), data.len());
+            return push(
+                &mut ha.keys,
+                ngx_hash_key_t {
+                    key: *key,
+                    key_hash,
+                    value,
+                },
+            );
+        };
+
+        let name = &mut data[skip..last];
+        name.make_ascii_lowercase();
+
+        if skip == 1 {
+            // check conflicts in exact hash for ".example.com"
+            let rc = add_name(ha.keys_hash, ha.temp_pool, name);
+            if rc != Status::NGX_OK.into() {
+                return rc;
+            }
+        }
+
+        // convert "*.example.com" to "com.example.", ".example.com" to "com.example" and
+        // "www.example.*" to "www.example"
+        let mut wc = Vec::with_capacity(last + 1);
+        let (hwc, keys) = if skip != 0 {
+            for label in name.rsplit(|c| *c == b'.') {
+                wc.extend_from_slice(label);
+                wc.push(b'.');
+            }
+            if skip == 1 {
+                wc.pop();
+            }
+            (&mut ha.dns_wc_head, ha.dns_wc_head_hash)
+        } else {
+            wc.extend_from_slice(name);
+            (&mut ha.dns_wc_tail, ha.dns_wc_tail_hash)
+        };
+
+        // check conflicts in wildcard hash
+        let rc = add_name(keys, ha.temp_pool, name);
+        if rc != Status::NGX_OK.into() {
+            return rc;
+        }
+
+        let p = ngx_pnalloc(ha.temp_pool, wc.len() + 1).cast::<u8>();
+        ptr::copy_nonoverlapping(wc.as_ptr(), p, wc.len());
+        *p.add(wc.len()) = 0;
+
+        let key = ngx_str_t { len: wc.len(), data: p };
+        push(
+            hwc,
+            ngx_hash_key_t {
+                key,
+                key_hash: 0,
+                value,
+            },
+        )
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_key(data: *mut u_char, len: usize) -> ngx_uint_t {
+        slice::from_raw_parts(data, len)
+            .iter()
+            .fold(0, |key: ngx_uint_t, c| key.wrapping_mul(31).wrapping_add(*c as _))
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_key_lc(data: *mut u_char, len: usize) -> ngx_uint_t {
+        slice::from_raw_parts(data, len).iter().fold(0, |key: ngx_uint_t, c| {
+            key.wrapping_mul(31).wrapping_add(c.to_ascii_lowercase() as _)
+        })
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_dns_strcmp(s1: *mut u_char, s2: *mut u_char) -> ngx_int_t {
+        let (a, b) = (
+            CStr::from_ptr(s1.cast()).to_bytes(),
+            CStr::from_ptr(s2.cast()).to_bytes(),
+        );
+        // '.' is the lowest character, and the shorter name is less than the longer one
+        let order = |c: &u8| match c.to_ascii_lowercase() {
+            b'.' => 1,
+            c => c as ngx_int_t + 1,
+        };
+        let a = a.iter().map(order).chain([0]);
+        let b = b.iter().map(order).chain([0]);
+        a.zip(b).map(|(x, y)| x - y).find(|x| *x != 0).unwrap_or(0)
+    }
+
+    /// Builds the hash with a bucket for each key, failing if there are more keys than `max_size`.
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_init(
+        hinit: *mut ngx_hash_init_t,
+        _names: *mut ngx_hash_key_t,
+        nelts: ngx_uint_t,
+    ) -> ngx_int_t {
+        if nelts > (*hinit).max_size {
+            return Status::NGX_ERROR.into();
+        }
+        (*(*hinit).hash).buckets = ngx_pcalloc((*hinit).pool, nelts * mem::size_of::<usize>()).cast();
+        (*(*hinit).hash).size = nelts;
+        Status::NGX_OK.into()
+    }
+
+    /// Builds the wildcard hash, failing if the names are not sorted.
+    #[no_mangle]
+    unsafe extern "C" fn ngx_hash_wildcard_init(
+        hinit: *mut ngx_hash_init_t,
+        names: *mut ngx_hash_key_t,
+        nelts: ngx_uint_t,
+    ) -> ngx_int_t {
+        let names = slice::from_raw_parts(names, nelts);
+        if names
+            .windows(2)
+            .any(|x| dns_cmp(&x[0].key, &x[1].key) == Ordering::Greater)
+        {
+            return Status::NGX_ERROR.into();
+        }
+
+        let wdc = ngx_pcalloc((*hinit).pool, mem::size_of::<ngx_hash_wildcard_t>()).cast::<ngx_hash_wildcard_t>();
+        (*wdc).hash.size = nelts;
+        (*hinit).hash = &mut (*wdc).hash;
+        Status::NGX_OK.into()
+    }
+
+    #[test]
+    fn add() {
+        let (pool, temp_pool) = (TestPool::new(), TestPool::new());
+        let mut builder = HashBuilder::<u32>::new(&pool, &temp_pool).unwrap();
+
+        builder.add(b"Example.COM", &1).unwrap();
+        assert!(matches!(builder.add(b"example.com", &2), Err(HashError::Duplicate)));
+        // the wildcard characters are a part of the exact key
+        builder.add(b"*.example.com", &3).unwrap();
+
+        let keys = unsafe { elts::<ngx_hash_key_t>(&builder.keys.keys) };
+        let names: Vec<_> = keys.iter().map(|hk| hk.key.as_bytes()).collect();
+        assert_eq!(names, [&b"example.com"[..], b"*.example.com"]);
+        assert_eq!(unsafe { *keys[0].value.cast::<u32>() }, 1);
+        assert_eq!(builder.keys.dns_wc_head.nelts, 0);
+        assert_eq!(builder.keys.dns_wc_tail.nelts, 0);
+    }
+
+    #[test]
+    fn add_host() {
+        let (pool, temp_pool) = (TestPool::new(), TestPool::new());
+        let mut builder = HashBuilder::<u32>::new(&pool, &temp_pool).unwrap();
+
+        builder.add_host(b"www.example.com", &1).unwrap();
+        builder.add_host(b"*.Example.ORG", &2).unwrap();
+        builder.add_host(b".example.net", &3).unwrap();
+        builder.add_host(b"www.example.*", &4).unwrap();
+
+        let names = |a: &ngx_array_t| -> Vec<_> {
+            unsafe { elts::<ngx_hash_key_t>(a) }
+                .iter()
+                .map(|hk| hk.key.as_bytes())
+                .collect()
+        };
+        assert_eq!(names(&builder.keys.keys), [&b"www.example.com"[..]]);
+        assert_eq!(names(&builder.keys.dns_wc_head), [&b"org.example."[..], b"net.example"]);
+        assert_eq!(names(&builder.keys.dns_wc_tail), [&b"www.example"[..]]);
+
+        for key in [
+            &b"WWW.example.com"[..],
+            b"*.example.org",
+            b".example.org",
+            b"example.net",
+            b"www.example.*",
+        ] {
+            assert!(
+                matches!(builder.add_host(key, &0), Err(HashError::Duplicate)),
+                "{key:?}"
+            );
+        }
+
+        for key in [
+            &b"*.example.*"[..],
+            b"www..example.com",
+            b"www.*.com",
+            b"*example.com",
+            b"www\0.com",
+        ] {
+            assert!(
+                matches!(builder.add_host(key, &0), Err(HashError::InvalidWildcard)),
+                "{key:?}"
+            );
+        }
+
+        assert_eq!(builder.keys.keys.nelts, 1);
+        assert_eq!(builder.keys.dns_wc_head.nelts, 2);
+        assert_eq!(builder.keys.dns_wc_tail.nelts, 1);
+    }
+
+    #[test]
+    fn add_alloc_error() {
+        let (pool, temp_pool) = (TestPool::new(), TestPool::new());
+        let mut builder = HashBuilder::<usize>::new(&pool, &temp_pool).unwrap();
+        let values: Vec<usize> = (0..=NALLOC).collect();
+
+        for (i, value) in values[..NALLOC].iter().enumerate() {
+            builder.add(std::format!("{i}.example.com").as_bytes(), value).unwrap();
+        }
+        assert!(matches!(
+            builder.add(b"example.com", &values[NALLOC]),
+            Err(HashError::Alloc)
+        ));
+    }
+
+    #[test]
+    fn build() {
+        let (pool, temp_pool) = (TestPool::new(), TestPool::new());
+
+        let hash = HashBuilder::<u32>::new(&pool, &temp_pool)
+            .unwrap()
+            .build(c"test", 8, 64)
+            .unwrap();
+        assert!(hash.is_empty());
+
+        let mut builder = HashBuilder::<u32>::new(&pool, &temp_pool).unwrap();
+        builder.add_host(b"example.com", &1).unwrap();
+        // added in the order different from the one required by ngx_hash_wildcard_init()
+        builder.add_host(b"*.b.example.com", &2).unwrap();
+        builder.add_host(b"*.example.org", &3).unwrap();
+        builder.add_host(b"*.a-b.example.com", &4).unwrap();
+        builder.add_host(b"www.example.*", &5).unwrap();
+
+        let hash = builder.build(c"test", 8, 64).unwrap();
+        assert!(!hash.is_empty());
+        assert_eq!(hash.combined.hash.size, 1);
+        assert_eq!(unsafe { (*hash.combined.wc_head).hash.size }, 3);
+        assert_eq!(unsafe { (*hash.combined.wc_tail).hash.size }, 1);
+
+        let mut builder = HashBuilder::<u32>::new(&pool, &temp_pool).unwrap();
+        builder.add(b"example.com", &1).unwrap();
+        builder.add(b"example.org", &2).unwrap();
+        assert!(matches!(builder.build(c"test", 1, 64), Err(HashError::Build)));
+    }
+}
@@ -0,0 +1,234 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use core::slice;
+
+use crate::allocator::{AllocError, Layout};
+use crate::core::Pool;
+use crate::ffi::*;
+
+/// Typed wrapper struct for an [`ngx_list_t`] with elements of type `T`.
+///
+/// The list is a sequence of fixed size parts allocated from the pool; the elements are never moved
+/// once added, and are never dropped.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#list>
+#[repr(transparent)]
+pub struct NgxList<T>(ngx_list_t, PhantomData<T>);
+
+impl<T> NgxList<T> {
+    /// Creates an empty list with `n` elements per part, allocating it from the pool.
+    ///
+    /// Returns `None` if the size of `n` elements overflows or the allocation fails.
+    #[allow(clippy::mut_from_ref)] // the list is a new allocation, not a part of the pool
+    pub fn create(pool: &Pool, n: usize) -> Option<&mut NgxList<T>> {
+        let n = n.max(1);
+        // ngx_list_create() does not check the size for overflow
+        Layout::array::<T>(n).ok()?;
+
+        let list = unsafe { ngx_list_create(pool.as_ngx_pool(), n, mem::size_of::<T>()) };
+        if list.is_null() {
+            return None;
+        }
+        Some(unsafe { Self::from_ngx_list_mut(list) })
+    }
+
+    /// Initializes an `ngx_list_t` in place with `n` elements of type `T` per part, as
+    /// `ngx_list_init` does.
+    ///
+    /// Returns `None` if the size of `n` elements overflows or the allocation fails.
+    pub fn init<'a>(list: &'a mut ngx_list_t, pool: &Pool, n: usize) -> Option<&'a mut NgxList<T>> {
+        let n = n.max(1);
+        let elts = pool.alloc(Layout::array::<T>(n).ok()?.size());
+        if elts.is_null() {
+            return None;
+        }
+
+        list.part.elts = elts;
+        list.part.nelts = 0;
+        list.part.next = ptr::null_mut();
+        list.last = &mut list.part;
+        list.size = mem::size_of::<T>();
+        list.nalloc = n;
+        list.pool = pool.as_ngx_pool();
+
+        Some(unsafe { Self::from_ngx_list_mut(list) })
+    }
+
+    /// Creates an `NgxList` reference from an `ngx_list_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid initialized `ngx_list_t` pointer is provided, the
+    /// elements of which are of type `T`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_list<'a>(list: *const ngx_list_t) -> &'a NgxList<T> {
+        assert!(!list.is_null());
+        debug_assert_eq!((*list).size, mem::size_of::<T>());
+        &*list.cast::<NgxList<T>>()
+    }
+
+    /// Creates a mutable `NgxList` reference from an `ngx_list_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid initialized `ngx_list_t` pointer is provided, the
+    /// elements of which are of type `T`, and that the list is not aliased for the lifetime `'a`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_list_mut<'a>(list: *mut ngx_list_t) -> &'a mut NgxList<T> {
+        assert!(!list.is_null());
+        debug_assert_eq!((*list).size, mem::size_of::<T>());
+        &mut *list.cast::<NgxList<T>>()
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_list_t`.
+    pub fn as_ngx_list(&self) -> *const ngx_list_t {
+        &self.0
+    }
+
+    /// Appends an element to the list, allocating a new part if necessary.
+    ///
+    /// Returns a reference to the added element.
+    pub fn push(&mut self, value: T) -> Result<&mut T, AllocError> {
+        let p = unsafe { ngx_list_push(&mut self.0) }.cast::<T>();
+        if p.is_null() {
+            return Err(AllocError);
+        }
+
+        unsafe {
+            ptr::write(p, value);
+            Ok(&mut *p)
+        }
+    }
+
+    /// Returns the number of elements in the list.
+    pub fn len(&self) -> usize {
+        self.parts().map(|x| x.len()).sum()
+    }
+
+    /// Returns `true` if the list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.parts().all(|x| x.is_empty())
+    }
+
+    /// Returns an iterator over the elements of the list.
+    pub fn iter(&self) -> NgxListIter<'_, T> {
+        NgxListIter {
+            part: &self.0.part,
+            elts: [].iter(),
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the elements of the list, allowing modification.
+    pub fn iter_mut(&mut self) -> NgxListIterMut<'_, T> {
+        NgxListIterMut {
+            part: &mut self.0.part,
+            elts: [].iter_mut(),
+            _p: PhantomData,
+        }
+    }
+
+    fn parts(&self) -> impl Iterator<Item = &[T]> {
+        let mut part: *const ngx_list_part_t = &self.0.part;
+        core::iter::from_fn(move || {
+            let p = unsafe { part.as_ref()? };
+            part = p.next;
+            Some(unsafe { part_slice(p) })
+        })
+    }
+}
+
+unsafe fn part_slice<'a, T>(part: &ngx_list_part_t) -> &'a [T] {
+    if part.nelts == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(part.elts.cast(), part.nelts)
+}
+
+unsafe fn part_slice_mut<'a, T>(part: &ngx_list_part_t) -> &'a mut [T] {
+    if part.nelts == 0 {
+        return &mut [];
+    }
+    slice::from_raw_parts_mut(part.elts.cast(), part.nelts)
+}
+
+/// Iterator over the elements of an [`NgxList`].
+pub struct NgxListIter<'a, T> {
+    part: *const ngx_list_part_t,
+    elts: slice::Iter<'a, T>,
+    _p: PhantomData<&'a NgxList<T>>,
+}
+
+impl<'a, T> Iterator for NgxListIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.elts.next() {
+                return Some(x);
+            }
+
+            let part = unsafe { self.part.as_ref()? };
+            self.part = part.next;
+            self.elts = unsafe { part_slice::<T>(part) }.iter();
+        }
+    }
+}
+
+/// Mutable iterator over the elements of an [`NgxList`].
+pub struct NgxListIterMut<'a, T> {
+    part: *mut ngx_list_part_t,
+    elts: slice::IterMut<'a, T>,
+    _p: PhantomData<&'a mut NgxList<T>>,
+}
+
+impl<'a, T> Iterator for NgxListIterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.elts.next() {
+                return Some(x);
+            }
+
+            let part = unsafe { self.part.as_ref()? };
+            self.part = part.next;
+            self.elts = unsafe { part_slice_mut::<T>(part) }.iter_mut();
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a NgxList<T> {
+    type Item = &'a T;
+    type IntoIter = NgxListIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut NgxList<T> {
+    type Item = &'a mut T;
+    type IntoIter = NgxListIterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::core::pool::tests::TestPool;
+
+    #[test]
+    fn init_overflow() {
+        let pool = TestPool::new();
+        let mut list: ngx_list_t = unsafe { mem::zeroed() };
+
+        assert!(NgxList::<u64>::init(&mut list, &pool, usize::MAX / 4).is_none());
+
+        let list = NgxList::<u64>::init(&mut list, &pool, 0).unwrap();
+        assert!(list.is_empty());
+        assert_eq!(unsafe { (*list.as_ngx_list()).nalloc }, 1);
+    }
+}
@@ -1,9 +1,33 @@
+mod array;
 mod buffer;
+mod chain;
+mod connection;
+mod file;
+mod hash;
+mod list;
 mod pool;
+mod queue;
+mod rbtree;
+mod shm;
+mod slab;
 mod status;
 mod string;
+#[cfg(all(feature = "std", ngx_feature = "threads"))]
+mod thread_pool;
 
+pub use array::*;
 pub use buffer::*;
+pub use chain::*;
+pub use connection::*;
+pub use file::*;
+pub use hash::*;
+pub use list::*;
 pub use pool::*;
+pub use queue::*;
+pub use rbtree::*;
+pub use shm::*;
+pub use slab::*;
 pub use status::*;
 pub use string::*;
+#[cfg(all(feature = "std", ngx_feature = "threads"))]
+pub use thread_pool::*;
@@ -1,30 +1,62 @@
-use core::ffi::c_void;
+use core::cell::UnsafeCell;
+use core::ffi::{c_ulong, c_void};
+use core::ops::Deref;
+use core::ptr::NonNull;
 use core::{mem, ptr};
 
-use crate::core::buffer::{Buffer, MemoryBuffer, TemporaryBuffer};
+use crate::allocator::{AllocError, Allocator, Layout};
+#[cfg(feature = "alloc")]
+use crate::allocator::{Box, Vec};
+use crate::core::buffer::{Buffer, FileBuffer, MemoryBuffer, TemporaryBuffer};
+use crate::core::file::OpenFileInfo;
 use crate::ffi::*;
 
-/// Wrapper struct for an [`ngx_pool_t`] pointer, providing methods for working with memory pools.
+/// Wrapper struct for an [`ngx_pool_t`], providing methods for working with memory pools.
+///
+/// The pool is always accessed by reference, borrowed from the object owning it, e.g. a request,
+/// a connection, a configuration or a cycle. The lifetime of the reference ensures that the memory
+/// allocated from the pool is not used after the owner is freed.
 ///
 /// See <https://nginx.org/en/docs/dev/development_guide.html#pool>
-pub struct Pool(*mut ngx_pool_t);
+#[repr(transparent)]
+pub struct Pool(UnsafeCell<ngx_pool_t>);
 
 impl Pool {
-    /// Creates a new `Pool` from an `ngx_pool_t` pointer.
+    /// Creates a `Pool` reference from an `ngx_pool_t` pointer.
     ///
     /// # Safety
-    /// The caller must ensure that a valid `ngx_pool_t` pointer is provided, pointing to valid memory and non-null.
+    /// The caller must ensure that a valid `ngx_pool_t` pointer is provided, pointing to valid memory and non-null,
+    /// and that the pool outlives the lifetime `'a`.
     /// A null argument will cause an assertion failure and panic.
-    pub unsafe fn from_ngx_pool(pool: *mut ngx_pool_t) -> Pool {
+    pub unsafe fn from_ngx_pool<'a>(pool: *mut ngx_pool_t) -> &'a Pool {
         assert!(!pool.is_null());
-        Pool(pool)
+        &*pool.cast::<Pool>()
+    }
+
+    /// Returns the pool of the configuration being parsed.
+    ///
+    /// The pool is the pool of the cycle being created and is destroyed on configuration reload.
+    pub fn from_ngx_conf(cf: &ngx_conf_t) -> &Pool {
+        // SAFETY: a configuration always has a valid pool, living at least as long as the configuration
+        unsafe { Pool::from_ngx_pool(cf.pool) }
+    }
+
+    /// Returns the pool of the cycle.
+    pub fn from_ngx_cycle(cycle: &ngx_cycle_t) -> &Pool {
+        // SAFETY: a cycle always has a valid pool, living at least as long as the cycle
+        unsafe { Pool::from_ngx_pool(cycle.pool) }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_pool_t`.
+    pub fn as_ngx_pool(&self) -> *mut ngx_pool_t {
+        self.0.get()
     }
 
     /// Creates a buffer of the specified size in the memory pool.
     ///
     /// Returns `Some(TemporaryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
-    pub fn create_buffer(&mut self, size: usize) -> Option<TemporaryBuffer> {
-        let buf = unsafe { ngx_create_temp_buf(self.0, size) };
+    pub fn create_buffer(&self, size: usize) -> Option<TemporaryBuffer> {
+        let buf = unsafe { ngx_create_temp_buf(self.as_ngx_pool(), size) };
         if buf.is_null() {
             return None;
         }
@@ -35,7 +67,7 @@ impl Pool {
     /// Creates a buffer from a string in the memory pool.
     ///
     /// Returns `Some(TemporaryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
-    pub fn create_buffer_from_str(&mut self, str: &str) -> Option<TemporaryBuffer> {
+    pub fn create_buffer_from_str(&self, str: &str) -> Option<TemporaryBuffer> {
         let mut buffer = self.create_buffer(str.len())?;
         unsafe {
             let buf = buffer.as_ngx_buf_mut();
@@ -48,15 +80,22 @@ impl Pool {
     /// Creates a buffer from a static string in the memory pool.
     ///
     /// Returns `Some(MemoryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
-    pub fn create_buffer_from_static_str(&mut self, str: &'static str) -> Option<MemoryBuffer> {
+    pub fn create_buffer_from_static_str(&self, str: &'static str) -> Option<MemoryBuffer> {
+        self.create_buffer_from_static_bytes(str.as_bytes())
+    }
+
+    /// Creates a buffer from a static byte slice in the memory pool.
+    ///
+    /// Returns `Some(MemoryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
+    pub fn create_buffer_from_static_bytes(&self, bytes: &'static [u8]) -> Option<MemoryBuffer> {
         let buf = self.calloc_type::<ngx_buf_t>();
         if buf.is_null() {
             return None;
         }
 
         // We cast away const, but buffers with the memory flag are read-only
-        let start = str.as_ptr() as *mut u8;
-        let end = unsafe { start.add(str.len()) };
+        let start = bytes.as_ptr() as *mut u8;
+        let end = unsafe { start.add(bytes.len()) };
 
         unsafe {
             (*buf).start = start;
@@ -69,14 +108,45 @@ impl Pool {
         Some(MemoryBuffer::from_ngx_buf(buf))
     }
 
+    /// Creates a buffer referencing the contents of an open file.
+    ///
+    /// The `name` and `log` are used for error reporting when the file is read or sent.
+    ///
+    /// Returns `Some(FileBuffer)` if the buffer is successfully created, or `None` if allocation fails.
+    pub fn create_file_buffer(&self, of: &OpenFileInfo, name: ngx_str_t, log: *mut ngx_log_t) -> Option<FileBuffer> {
+        let file = self.calloc(mem::size_of::<ngx_file_t>()) as *mut ngx_file_t;
+        if file.is_null() {
+            return None;
+        }
+
+        let buf = self.calloc_type::<ngx_buf_t>();
+        if buf.is_null() {
+            return None;
+        }
+
+        unsafe {
+            (*file).fd = of.fd();
+            (*file).name = name;
+            (*file).log = log;
+            (*file).set_directio(if of.is_directio() { 1 } else { 0 });
+
+            (*buf).file = file;
+            (*buf).file_pos = 0;
+            (*buf).file_last = of.size();
+            (*buf).set_in_file(if of.size() > 0 { 1 } else { 0 });
+        }
+
+        Some(FileBuffer::from_ngx_buf(buf))
+    }
+
     /// Adds a cleanup handler for a value in the memory pool.
     ///
     /// Returns `Ok(())` if the cleanup handler is successfully added, or `Err(())` if the cleanup handler cannot be added.
     ///
     /// # Safety
     /// This function is marked as unsafe because it involves raw pointer manipulation.
-    unsafe fn add_cleanup_for_value<T>(&mut self, value: *mut T) -> Result<(), ()> {
-        let cln = ngx_pool_cleanup_add(self.0, 0);
+    unsafe fn add_cleanup_for_value<T>(&self, value: *mut T) -> Result<(), ()> {
+        let cln = ngx_pool_cleanup_add(self.as_ngx_pool(), 0);
         if cln.is_null() {
             return Err(());
         }
@@ -90,15 +160,15 @@ impl Pool {
     /// The resulting pointer is aligned to a platform word size.
     ///
     /// Returns a raw pointer to the allocated memory.
-    pub fn alloc(&mut self, size: usize) -> *mut c_void {
-        unsafe { ngx_palloc(self.0, size) }
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_palloc(self.as_ngx_pool(), size) }
     }
 
     /// Allocates memory for a type from the pool.
     /// The resulting pointer is aligned to a platform word size.
     ///
     /// Returns a typed pointer to the allocated memory.
-    pub fn alloc_type<T: Copy>(&mut self) -> *mut T {
+    pub fn alloc_type<T: Copy>(&self) -> *mut T {
         self.alloc(mem::size_of::<T>()) as *mut T
     }
 
@@ -106,29 +176,29 @@ impl Pool {
     /// The resulting pointer is aligned to a platform word size.
     ///
     /// Returns a raw pointer to the allocated memory.
-    pub fn calloc(&mut self, size: usize) -> *mut c_void {
-        unsafe { ngx_pcalloc(self.0, size) }
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_pcalloc(self.as_ngx_pool(), size) }
     }
 
     /// Allocates zeroed memory for a type from the pool.
     /// The resulting pointer is aligned to a platform word size.
     ///
     /// Returns a typed pointer to the allocated memory.
-    pub fn calloc_type<T: Copy>(&mut self) -> *mut T {
+    pub fn calloc_type<T: Copy>(&self) -> *mut T {
         self.calloc(mem::size_of::<T>()) as *mut T
     }
 
     /// Allocates unaligned memory from the pool of the specified size.
     ///
     /// Returns a raw pointer to the allocated memory.
-    pub fn alloc_unaligned(&mut self, size: usize) -> *mut c_void {
-        unsafe { ngx_pnalloc(self.0, size) }
+    pub fn alloc_unaligned(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_pnalloc(self.as_ngx_pool(), size) }
     }
 
     /// Allocates unaligned memory for a type from the pool.
     ///
     /// Returns a typed pointer to the allocated memory.
-    pub fn alloc_type_unaligned<T: Copy>(&mut self) -> *mut T {
+    pub fn alloc_type_unaligned<T: Copy>(&self) -> *mut T {
         self.alloc_unaligned(mem::size_of::<T>()) as *mut T
     }
 
@@ -138,7 +208,7 @@ impl Pool {
     ///
     /// # Safety
     /// This function is marked as unsafe because it involves raw pointer manipulation.
-    pub fn allocate<T>(&mut self, value: T) -> *mut T {
+    pub fn allocate<T>(&self, value: T) -> *mut T {
         unsafe {
             let p = self.alloc(mem::size_of::<T>()) as *mut T;
             ptr::write(p, value);
@@ -151,6 +221,119 @@ impl Pool {
     }
 }
 
+/// Memory pool owned by Rust code.
+///
+/// The pool is created with `ngx_create_pool` and destroyed with `ngx_destroy_pool` when dropped,
+/// running all the registered cleanup handlers and releasing the allocated memory.
+///
+/// Useful for the objects that are not bound to a request, connection or cycle, e.g. background
+/// jobs or state kept across several requests.
+pub struct OwnedPool(NonNull<Pool>);
+
+impl OwnedPool {
+    /// Creates a new pool of blocks of the specified `size`.
+    ///
+    /// The `size` should be at least `NGX_MIN_POOL_SIZE`; `NGX_DEFAULT_POOL_SIZE` is a reasonable default.
+    ///
+    /// Returns `Err` if the allocation fails.
+    ///
+    /// # Safety
+    /// The caller must provide a valid `ngx_log_t` pointer that outlives the pool.
+    pub unsafe fn new(size: usize, log: *mut ngx_log_t) -> Result<Self, AllocError> {
+        let pool = ngx_create_pool(size, log);
+        let pool = NonNull::new(pool.cast::<Pool>()).ok_or(AllocError)?;
+        Ok(Self(pool))
+    }
+
+    /// Releases all the memory allocated from the pool and runs the cleanup handlers,
+    /// leaving the pool empty and ready for reuse.
+    ///
+    /// Exclusive access ensures that no references to the pool, and thus to the memory allocated
+    /// from it, are alive.
+    pub fn reset(&mut self) {
+        unsafe {
+            let p = self.as_ngx_pool();
+            run_cleanups(p);
+            ngx_reset_pool(p);
+        }
+    }
+}
+
+impl Deref for OwnedPool {
+    type Target = Pool;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the pool is valid until dropped
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Drop for OwnedPool {
+    fn drop(&mut self) {
+        // SAFETY: the pool is valid and is no longer referenced
+        unsafe { ngx_destroy_pool(self.as_ngx_pool()) };
+    }
+}
+
+/// Runs and removes the cleanup handlers of the pool.
+///
+/// `ngx_reset_pool` releases the memory without running the cleanups, unlike `ngx_destroy_pool`.
+unsafe fn run_cleanups(pool: *mut ngx_pool_t) {
+    let mut c = (*pool).cleanup;
+    while !c.is_null() {
+        if let Some(handler) = (*c).handler {
+            handler((*c).data);
+        }
+        c = (*c).next;
+    }
+    (*pool).cleanup = ptr::null_mut();
+}
+
+/// The alignment of the pointers returned by `ngx_palloc`.
+const NGX_ALIGNMENT: usize = mem::size_of::<c_ulong>();
+
+/// Allocator API implementation for the memory pool.
+///
+/// The memory is released when the pool is destroyed. Deallocation of individual blocks is only
+/// possible for the allocations larger than the pool block size and is a no-op otherwise.
+unsafe impl Allocator for Pool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            // SAFETY: alignment is always a non-zero power of two
+            let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+
+        let p = if layout.align() <= NGX_ALIGNMENT {
+            unsafe { ngx_palloc(self.as_ngx_pool(), layout.size()) }
+        } else {
+            unsafe { ngx_pmemalign(self.as_ngx_pool(), layout.size(), layout.align()) }
+        };
+
+        let p = NonNull::new(p.cast::<u8>()).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(p, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            ngx_pfree(self.as_ngx_pool(), ptr.as_ptr().cast());
+        }
+    }
+}
+
+/// A [`Box`] allocated from the memory pool.
+#[cfg(feature = "alloc")]
+pub type PoolBox<'a, T> = Box<T, &'a Pool>;
+
+/// A [`Vec`] allocated from the memory pool.
+///
+/// The pool does not release the small blocks, so the previous buffer is kept until the pool is
+/// destroyed whenever the vector grows. Pushing `n` elements one by one consumes about twice the
+/// memory of the final buffer, plus the copying; prefer [`Vec::with_capacity_in`] when the size is
+/// known in advance.
+#[cfg(feature = "alloc")]
+pub type PoolVec<'a, T> = Vec<T, &'a Pool>;
+
 /// Cleanup handler for a specific type `T`.
 ///
 /// This function is called when cleaning up a value of type `T` in an FFI context.
@@ -164,3 +347,145 @@ impl Pool {
 unsafe extern "C" fn cleanup_type<T>(data: *mut c_void) {
     ptr::drop_in_place(data as *mut T);
 }
+
+/// Memory pool for the unit tests, which are not linked with nginx.
+///
+/// The pool functions are replaced with the global allocator, and the memory is released when the
+/// pool is dropped.
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod tests {
+    use core::cell::RefCell;
+
+    use std::alloc;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::core::Status;
+
+    std::thread_local! {
+        static ALLOCATIONS: RefCell<Vec<(*mut ngx_pool_t, *mut u8, alloc::Layout)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub(crate) struct TestPool(Box<Pool>);
+
+    impl TestPool {
+        pub(crate) fn new() -> Self {
+            // SAFETY: only the `chain` and `cleanup` fields are used by the functions below
+            Self(Box::new(Pool(UnsafeCell::new(unsafe { mem::zeroed() }))))
+        }
+    }
+
+    impl Deref for TestPool {
+        type Target = Pool;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl Drop for TestPool {
+        fn drop(&mut self) {
+            let pool = self.as_ngx_pool();
+            unsafe { run_cleanups(pool) };
+
+            ALLOCATIONS.with(|x| {
+                x.borrow_mut().retain(|&(p, ptr, layout)| {
+                    if p != pool {
+                        return true;
+                    }
+                    unsafe { alloc::dealloc(ptr, layout) };
+                    false
+                })
+            });
+        }
+    }
+
+    fn palloc(pool: *mut ngx_pool_t, size: usize, align: usize) -> *mut c_void {
+        let Ok(layout) = alloc::Layout::from_size_align(size.max(1), align) else {
+            return ptr::null_mut();
+        };
+
+        let p = unsafe { alloc::alloc_zeroed(layout) };
+        if !p.is_null() {
+            ALLOCATIONS.with(|x| x.borrow_mut().push((pool, p, layout)));
+        }
+        p.cast()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_palloc(pool: *mut ngx_pool_t, size: usize) -> *mut c_void {
+        palloc(pool, size, NGX_ALIGNMENT)
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_pnalloc(pool: *mut ngx_pool_t, size: usize) -> *mut c_void {
+        palloc(pool, size, 1)
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_pcalloc(pool: *mut ngx_pool_t, size: usize) -> *mut c_void {
+        palloc(pool, size, NGX_ALIGNMENT)
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_pmemalign(pool: *mut ngx_pool_t, size: usize, alignment: usize) -> *mut c_void {
+        palloc(pool, size, alignment)
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_pfree(_pool: *mut ngx_pool_t, _p: *mut c_void) -> ngx_int_t {
+        // as for the allocations smaller than the pool block
+        Status::NGX_DECLINED.into()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_pool_cleanup_add(pool: *mut ngx_pool_t, size: usize) -> *mut ngx_pool_cleanup_t {
+        let c = ngx_palloc(pool, mem::size_of::<ngx_pool_cleanup_t>()).cast::<ngx_pool_cleanup_t>();
+        if c.is_null() {
+            return ptr::null_mut();
+        }
+
+        (*c).data = if size > 0 {
+            ngx_palloc(pool, size)
+        } else {
+            ptr::null_mut()
+        };
+        (*c).handler = None;
+        (*c).next = (*pool).cleanup;
+        (*pool).cleanup = c;
+        c
+    }
+
+    #[test]
+    fn allocate() {
+        let pool = TestPool::new();
+
+        let mut v = PoolVec::new_in(&*pool);
+        for i in 0..100u32 {
+            v.push(i);
+        }
+        assert!(v.iter().copied().eq(0..100));
+
+        let mut v = PoolVec::with_capacity_in(100, &*pool);
+        let p = v.as_ptr();
+        v.extend(0..100u32);
+        assert_eq!(v.as_ptr(), p);
+
+        let b = PoolBox::new_in(0x1234u64, &*pool);
+        assert_eq!(*b, 0x1234);
+        assert_eq!(ptr::from_ref(&*b) as usize % mem::align_of::<u64>(), 0);
+    }
+
+    #[test]
+    fn cleanup() {
+        let value = std::rc::Rc::new(());
+        {
+            let pool = TestPool::new();
+            let p = pool.allocate(value.clone());
+            assert!(!p.is_null());
+            assert_eq!(std::rc::Rc::strong_count(&value), 2);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,275 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::offset_of;
+use core::ptr::{self, NonNull};
+
+use crate::ffi::*;
+
+/// Types containing an intrusive `ngx_queue_t` link.
+///
+/// # Safety
+/// The implementation must return the link embedded in the entry, and [`QueueEntry::from_queue_link`]
+/// must be the exact inverse of [`QueueEntry::queue_link`], as with the `ngx_queue_data` macro.
+pub unsafe trait QueueEntry {
+    /// Returns the queue link of the entry.
+    fn queue_link(this: NonNull<Self>) -> NonNull<ngx_queue_t>;
+
+    /// Returns the entry containing the queue link.
+    ///
+    /// # Safety
+    /// The link must be embedded in an entry of this type.
+    unsafe fn from_queue_link(link: NonNull<ngx_queue_t>) -> NonNull<Self>;
+}
+
+unsafe impl QueueEntry for ngx_event_t {
+    fn queue_link(this: NonNull<Self>) -> NonNull<ngx_queue_t> {
+        unsafe { NonNull::new_unchecked(ptr::addr_of_mut!((*this.as_ptr()).queue)) }
+    }
+
+    unsafe fn from_queue_link(link: NonNull<ngx_queue_t>) -> NonNull<Self> {
+        NonNull::new_unchecked(link.as_ptr().byte_sub(offset_of!(ngx_event_t, queue))).cast()
+    }
+}
+
+/// Wrapper struct for the sentinel of an intrusive [`ngx_queue_t`] list of `T`.
+///
+/// The queue does not own the entries: these are allocated and released by the caller, and must stay
+/// in place while linked. The same queue can thus be used for the process-local lists, e.g. the posted
+/// events, and for the lists in shared memory.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#queue>
+#[repr(transparent)]
+pub struct Queue<T: QueueEntry>(UnsafeCell<ngx_queue_t>, PhantomData<T>);
+
+impl<T: QueueEntry> Queue<T> {
+    /// Creates a `Queue` reference from an `ngx_queue_t` sentinel pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid `ngx_queue_t` pointer is provided, that it is not moved
+    /// for the lifetime `'a` and that all the linked entries are of type `T`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_queue<'a>(queue: *mut ngx_queue_t) -> &'a Queue<T> {
+        assert!(!queue.is_null());
+        &*queue.cast::<Queue<T>>()
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_queue_t` sentinel.
+    pub fn as_ngx_queue(&self) -> *mut ngx_queue_t {
+        self.0.get()
+    }
+
+    /// Initializes the queue as empty, forgetting all the linked entries.
+    pub fn init(&self) {
+        let q = self.as_ngx_queue();
+        unsafe {
+            (*q).prev = q;
+            (*q).next = q;
+        }
+    }
+
+    /// Returns `true` if the queue contains no entries.
+    pub fn is_empty(&self) -> bool {
+        let q = self.as_ngx_queue();
+        unsafe { (*q).prev == q }
+    }
+
+    /// Returns the first entry of the queue.
+    pub fn head(&self) -> Option<NonNull<T>> {
+        self.entry(unsafe { (*self.as_ngx_queue()).next })
+    }
+
+    /// Returns the last entry of the queue.
+    pub fn tail(&self) -> Option<NonNull<T>> {
+        self.entry(unsafe { (*self.as_ngx_queue()).prev })
+    }
+
+    /// Inserts the entry at the beginning of the queue.
+    ///
+    /// # Safety
+    /// The entry must not be linked to any queue and must stay in place until removed.
+    pub unsafe fn insert_head(&self, entry: NonNull<T>) {
+        let q = self.as_ngx_queue();
+        let x = T::queue_link(entry).as_ptr();
+
+        (*x).next = (*q).next;
+        (*(*x).next).prev = x;
+        (*x).prev = q;
+        (*q).next = x;
+    }
+
+    /// Inserts the entry at the end of the queue.
+    ///
+    /// # Safety
+    /// The entry must not be linked to any queue and must stay in place until removed.
+    pub unsafe fn insert_tail(&self, entry: NonNull<T>) {
+        let q = self.as_ngx_queue();
+        let x = T::queue_link(entry).as_ptr();
+
+        (*x).prev = (*q).prev;
+        (*(*x).prev).next = x;
+        (*x).next = q;
+        (*q).prev = x;
+    }
+
+    /// Removes the entry from the queue it is linked to.
+    ///
+    /// # Safety
+    /// The entry must be linked to a queue.
+    pub unsafe fn remove(entry: NonNull<T>) {
+        let x = T::queue_link(entry).as_ptr();
+
+        (*(*x).next).prev = (*x).prev;
+        (*(*x).prev).next = (*x).next;
+        (*x).prev = ptr::null_mut();
+        (*x).next = ptr::null_mut();
+    }
+
+    /// Moves the entry linked to this queue to the beginning of the queue.
+    ///
+    /// Combined with [`Queue::pop_tail`], implements the least recently used list: the entries are
+    /// moved to the head on access and the expired ones are taken from the tail.
+    ///
+    /// # Safety
+    /// The entry must be linked to this queue.
+    pub unsafe fn move_to_head(&self, entry: NonNull<T>) {
+        Self::remove(entry);
+        self.insert_head(entry);
+    }
+
+    /// Removes and returns the last entry of the queue.
+    pub fn pop_tail(&self) -> Option<NonNull<T>> {
+        let entry = self.tail()?;
+        unsafe { Self::remove(entry) };
+        Some(entry)
+    }
+
+    /// Removes and returns the first entry of the queue.
+    pub fn pop_head(&self) -> Option<NonNull<T>> {
+        let entry = self.head()?;
+        unsafe { Self::remove(entry) };
+        Some(entry)
+    }
+
+    /// Returns an iterator over the entries from the head to the tail.
+    ///
+    /// The queue must not be modified while iterating.
+    pub fn iter(&self) -> QueueIter<'_, T> {
+        QueueIter {
+            queue: self,
+            link: unsafe { (*self.as_ngx_queue()).next },
+        }
+    }
+
+    fn entry(&self, link: *mut ngx_queue_t) -> Option<NonNull<T>> {
+        if link == self.as_ngx_queue() {
+            return None;
+        }
+        NonNull::new(link).map(|x| unsafe { T::from_queue_link(x) })
+    }
+}
+
+/// Iterator over the entries of a [`Queue`].
+pub struct QueueIter<'a, T: QueueEntry> {
+    queue: &'a Queue<T>,
+    link: *mut ngx_queue_t,
+}
+
+impl<T: QueueEntry> Iterator for QueueIter<'_, T> {
+    type Item = NonNull<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.queue.entry(self.link)?;
+        self.link = unsafe { (*self.link).next };
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem;
+
+    use super::*;
+
+    struct Item {
+        value: u32,
+        link: ngx_queue_t,
+    }
+
+    unsafe impl QueueEntry for Item {
+        fn queue_link(this: NonNull<Self>) -> NonNull<ngx_queue_t> {
+            unsafe { NonNull::new_unchecked(ptr::addr_of_mut!((*this.as_ptr()).link)) }
+        }
+
+        unsafe fn from_queue_link(link: NonNull<ngx_queue_t>) -> NonNull<Self> {
+            NonNull::new_unchecked(link.as_ptr().byte_sub(offset_of!(Item, link))).cast()
+        }
+    }
+
+    fn item(value: u32) -> Item {
+        Item {
+            value,
+            link: unsafe { mem::zeroed() },
+        }
+    }
+
+    fn values(queue: &Queue<Item>) -> [u32; 4] {
+        let mut out = [0; 4];
+        for (i, x) in queue.iter().enumerate() {
+            out[i] = unsafe { x.as_ref().value };
+        }
+        out
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut sentinel: ngx_queue_t = unsafe { mem::zeroed() };
+        let queue = unsafe { Queue::<Item>::from_ngx_queue(&mut sentinel) };
+        queue.init();
+        assert!(queue.is_empty());
+        assert!(queue.head().is_none());
+
+        let mut items = [item(1), item(2), item(3)];
+        let [a, b, c] = items.each_mut().map(NonNull::from);
+
+        unsafe {
+            queue.insert_tail(b);
+            queue.insert_head(a);
+            queue.insert_tail(c);
+        }
+        assert!(!queue.is_empty());
+        assert_eq!(values(queue), [1, 2, 3, 0]);
+        assert_eq!(queue.head(), Some(a));
+        assert_eq!(queue.tail(), Some(c));
+
+        unsafe { Queue::remove(b) };
+        assert_eq!(values(queue), [1, 3, 0, 0]);
+
+        assert_eq!(queue.pop_head(), Some(a));
+        assert_eq!(queue.pop_tail(), Some(c));
+        assert!(queue.pop_tail().is_none());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn lru() {
+        let mut sentinel: ngx_queue_t = unsafe { mem::zeroed() };
+        let queue = unsafe { Queue::<Item>::from_ngx_queue(&mut sentinel) };
+        queue.init();
+
+        let mut items = [item(1), item(2), item(3), item(4)];
+        for x in items.iter_mut() {
+            unsafe { queue.insert_head(NonNull::from(x)) };
+        }
+        assert_eq!(values(queue), [4, 3, 2, 1]);
+
+        unsafe {
+            queue.move_to_head(NonNull::from(&mut items[1]));
+            queue.move_to_head(NonNull::from(&mut items[0]));
+        }
+        assert_eq!(values(queue), [1, 2, 4, 3]);
+
+        let expired = queue.pop_tail().map(|x| unsafe { x.as_ref().value });
+        assert_eq!(expired, Some(3));
+        assert_eq!(values(queue), [1, 2, 4, 0]);
+    }
+}
@@ -0,0 +1,693 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use crate::allocator::{AllocError, Allocator, Layout};
+use crate::ffi::*;
+
+/// Tree node: the nginx node is placed first to allow casting between the node pointers.
+#[repr(C)]
+struct Node<K, V> {
+    node: ngx_rbtree_node_t,
+    key: K,
+    value: V,
+}
+
+/// Tree header. The root and sentinel pointers of the tree reference the header itself, thus it must
+/// not be moved.
+///
+/// The header also keeps the number of elements, so that all the state of the tree is allocated with
+/// `A`, e.g. in shared memory.
+#[repr(C)]
+struct Header {
+    tree: ngx_rbtree_t,
+    sentinel: ngx_rbtree_node_t,
+    len: usize,
+}
+
+/// Red-black tree with the nodes allocated with `A`.
+///
+/// The nodes are ordered by the hash of the key and then by the key itself, following the pattern used
+/// by nginx for the string-keyed trees (`ngx_str_rbtree_insert_value`). The iteration order is thus
+/// unspecified.
+///
+/// The tree can be placed in shared memory when used with a [`SlabPool`](crate::core::SlabPool)
+/// allocator.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#red_black_tree>
+pub struct RbTree<K, V, A: Allocator> {
+    header: NonNull<Header>,
+    alloc: A,
+    _p: PhantomData<Node<K, V>>,
+}
+
+impl<K, V, A> RbTree<K, V, A>
+where
+    K: Hash + Ord,
+    A: Allocator,
+{
+    /// Creates an empty tree allocating from `alloc`.
+    pub fn try_new_in(alloc: A) -> Result<Self, AllocError> {
+        let header = alloc.allocate(Layout::new::<Header>())?.cast::<Header>();
+
+        unsafe {
+            let h = header.as_ptr();
+            ptr::write_bytes(h, 0, 1);
+
+            // ngx_rbtree_init(): the sentinel is black
+            let sentinel = ptr::addr_of_mut!((*h).sentinel);
+            (*sentinel).color = 0;
+            (*h).tree.root = sentinel;
+            (*h).tree.sentinel = sentinel;
+            (*h).tree.insert = Some(insert_value::<K, V>);
+        }
+
+        Ok(Self {
+            header,
+            alloc,
+            _p: PhantomData,
+        })
+    }
+
+    /// Inserts a key-value pair into the tree.
+    ///
+    /// Returns the previous value if the key was already present.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, AllocError> {
+        if let Some(node) = self.lookup(&key) {
+            let node = unsafe { &mut *node.as_ptr() };
+            return Ok(Some(mem::replace(&mut node.value, value)));
+        }
+
+        let node = self.alloc.allocate(Layout::new::<Node<K, V>>())?.cast::<Node<K, V>>();
+
+        unsafe {
+            let n = node.as_ptr();
+            ptr::write_bytes(ptr::addr_of_mut!((*n).node), 0, 1);
+            (*n).node.key = hash_key(&key);
+            ptr::write(ptr::addr_of_mut!((*n).key), key);
+            ptr::write(ptr::addr_of_mut!((*n).value), value);
+
+            ngx_rbtree_insert(self.tree(), ptr::addr_of_mut!((*n).node));
+        }
+
+        unsafe { (*self.header.as_ptr()).len += 1 };
+        Ok(None)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        self.lookup(key).map(|n| unsafe { &(*n.as_ptr()).value })
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        self.lookup(key).map(|n| unsafe { &mut (*n.as_ptr()).value })
+    }
+
+    /// Removes a key from the tree, returning the value if the key was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        let node = self.lookup(key)?;
+
+        unsafe {
+            let n = node.as_ptr();
+            ngx_rbtree_delete(self.tree(), ptr::addr_of_mut!((*n).node));
+
+            ptr::drop_in_place(ptr::addr_of_mut!((*n).key));
+            let value = ptr::read(ptr::addr_of!((*n).value));
+            self.alloc.deallocate(node.cast(), Layout::new::<Node<K, V>>());
+
+            (*self.header.as_ptr()).len -= 1;
+            Some(value)
+        }
+    }
+
+    fn lookup<Q>(&self, key: &Q) -> Option<NonNull<Node<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Ord + ?Sized,
+    {
+        let hash = hash_key(key);
+
+        unsafe {
+            let tree = self.tree();
+            let sentinel = (*tree).sentinel;
+            let mut node = (*tree).root;
+
+            while node != sentinel {
+                let n = node.cast::<Node<K, V>>();
+
+                let ord = hash.cmp(&(*node).key).then_with(|| key.cmp((*n).key.borrow()));
+                node = match ord {
+                    Ordering::Less => (*node).left,
+                    Ordering::Greater => (*node).right,
+                    Ordering::Equal => return NonNull::new(n),
+                };
+            }
+        }
+
+        None
+    }
+}
+
+impl<K, V, A: Allocator> RbTree<K, V, A> {
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        unsafe { self.header.as_ref().len }
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the entries of the tree.
+    pub fn iter(&self) -> RbTreeIter<'_, K, V> {
+        let tree = self.tree();
+        let mut node = unsafe { (*tree).root };
+        let sentinel = unsafe { (*tree).sentinel };
+
+        // ngx_rbtree_min()
+        if node != sentinel {
+            while unsafe { (*node).left } != sentinel {
+                node = unsafe { (*node).left };
+            }
+        }
+
+        RbTreeIter {
+            tree,
+            node,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_rbtree_t`.
+    pub fn as_ngx_rbtree(&self) -> *mut ngx_rbtree_t {
+        self.tree()
+    }
+
+    fn tree(&self) -> *mut ngx_rbtree_t {
+        unsafe { ptr::addr_of_mut!((*self.header.as_ptr()).tree) }
+    }
+}
+
+impl<K, V, A: Allocator> Drop for RbTree<K, V, A> {
+    fn drop(&mut self) {
+        let tree = self.tree();
+
+        unsafe {
+            let sentinel = (*tree).sentinel;
+
+            loop {
+                let node = (*tree).root;
+                if node == sentinel {
+                    break;
+                }
+                ngx_rbtree_delete(tree, node);
+
+                let n = node.cast::<Node<K, V>>();
+                ptr::drop_in_place(n);
+                self.alloc
+                    .deallocate(NonNull::new_unchecked(n).cast(), Layout::new::<Node<K, V>>());
+            }
+
+            self.alloc.deallocate(self.header.cast(), Layout::new::<Header>());
+        }
+    }
+}
+
+/// Iterator over the entries of an [`RbTree`].
+pub struct RbTreeIter<'a, K, V> {
+    tree: *mut ngx_rbtree_t,
+    node: *mut ngx_rbtree_node_t,
+    _p: PhantomData<(&'a K, &'a V)>,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for RbTreeIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            if self.node.is_null() || self.node == (*self.tree).sentinel {
+                return None;
+            }
+
+            let n = self.node.cast::<Node<K, V>>();
+            self.node = ngx_rbtree_next(self.tree, self.node);
+
+            Some((&(*n).key, &(*n).value))
+        }
+    }
+}
+
+/// Node insertion callback, see `ngx_str_rbtree_insert_value`.
+unsafe extern "C" fn insert_value<K: Ord, V>(
+    mut temp: *mut ngx_rbtree_node_t,
+    node: *mut ngx_rbtree_node_t,
+    sentinel: *mut ngx_rbtree_node_t,
+) {
+    let n = node.cast::<Node<K, V>>();
+
+    let p = loop {
+        let t = temp.cast::<Node<K, V>>();
+
+        let ord = (*node).key.cmp(&(*temp).key).then_with(|| (*n).key.cmp(&(*t).key));
+        let p = if ord == Ordering::Less {
+            ptr::addr_of_mut!((*temp).left)
+        } else {
+            ptr::addr_of_mut!((*temp).right)
+        };
+
+        if *p == sentinel {
+            break p;
+        }
+
+        temp = *p;
+    };
+
+    *p = node;
+    (*node).parent = temp;
+    (*node).left = sentinel;
+    (*node).right = sentinel;
+    // ngx_rbt_red()
+    (*node).color = 1;
+}
+
+/// Hashes the key with FNV-1a, which is stable across processes unlike the `std` default hasher.
+fn hash_key<Q: Hash + ?Sized>(key: &Q) -> ngx_rbtree_key_t {
+    let mut hasher = Fnv1a(0xcbf29ce484222325);
+    key.hash(&mut hasher);
+    hasher.finish() as ngx_rbtree_key_t
+}
+
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use allocator_api2::alloc::Global;
+
+    use super::*;
+
+    // the tree functions, as implemented in src/core/ngx_rbtree.c
+
+    type NodePtr = *mut ngx_rbtree_node_t;
+
+    unsafe fn is_red(node: NodePtr) -> bool {
+        (*node).color != 0
+    }
+
+    unsafe fn min(mut node: NodePtr, sentinel: NodePtr) -> NodePtr {
+        while (*node).left != sentinel {
+            node = (*node).left;
+        }
+        node
+    }
+
+    unsafe fn left_rotate(root: *mut NodePtr, sentinel: NodePtr, node: NodePtr) {
+        let temp = (*node).right;
+        (*node).right = (*temp).left;
+        if (*temp).left != sentinel {
+            (*(*temp).left).parent = node;
+        }
+        (*temp).parent = (*node).parent;
+        if node == *root {
+            *root = temp;
+        } else if node == (*(*node).parent).left {
+            (*(*node).parent).left = temp;
+        } else {
+            (*(*node).parent).right = temp;
+        }
+        (*temp).left = node;
+        (*node).parent = temp;
+    }
+
+    unsafe fn right_rotate(root: *mut NodePtr, sentinel: NodePtr, node: NodePtr) {
+        let temp = (*node).left;
+        (*node).left = (*temp).right;
+        if (*temp).right != sentinel {
+            (*(*temp).right).parent = node;
+        }
+        (*temp).parent = (*node).parent;
+        if node == *root {
+            *root = temp;
+        } else if node == (*(*node).parent).right {
+            (*(*node).parent).right = temp;
+        } else {
+            (*(*node).parent).left = temp;
+        }
+        (*temp).right = node;
+        (*node).parent = temp;
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_rbtree_insert(tree: *mut ngx_rbtree_t, mut node: NodePtr) {
+        let root = ptr::addr_of_mut!((*tree).root);
+        let sentinel = (*tree).sentinel;
+
+        if *root == sentinel {
+            (*node).parent = ptr::null_mut();
+            (*node).left = sentinel;
+            (*node).right = sentinel;
+            (*node).color = 0;
+            *root = node;
+            return;
+        }
+
+        ((*tree).insert.unwrap())(*root, node, sentinel);
+
+        while node != *root && is_red((*node).parent) {
+            let parent = (*node).parent;
+            let grandparent = (*parent).parent;
+
+            if parent == (*grandparent).left {
+                let temp = (*grandparent).right;
+                if is_red(temp) {
+                    (*parent).color = 0;
+                    (*temp).color = 0;
+                    (*grandparent).color = 1;
+                    node = grandparent;
+                } else {
+                    if node == (*parent).right {
+                        node = parent;
+                        left_rotate(root, sentinel, node);
+                    }
+                    (*(*node).parent).color = 0;
+                    (*(*(*node).parent).parent).color = 1;
+                    right_rotate(root, sentinel, (*(*node).parent).parent);
+                }
+            } else {
+                let temp = (*grandparent).left;
+                if is_red(temp) {
+                    (*parent).color = 0;
+                    (*temp).color = 0;
+                    (*grandparent).color = 1;
+                    node = grandparent;
+                } else {
+                    if node == (*parent).left {
+                        node = parent;
+                        right_rotate(root, sentinel, node);
+                    }
+                    (*(*node).parent).color = 0;
+                    (*(*(*node).parent).parent).color = 1;
+                    left_rotate(root, sentinel, (*(*node).parent).parent);
+                }
+            }
+        }
+
+        (**root).color = 0;
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_rbtree_delete(tree: *mut ngx_rbtree_t, node: NodePtr) {
+        let root = ptr::addr_of_mut!((*tree).root);
+        let sentinel = (*tree).sentinel;
+
+        let (subst, mut temp) = if (*node).left == sentinel {
+            (node, (*node).right)
+        } else if (*node).right == sentinel {
+            (node, (*node).left)
+        } else {
+            let subst = min((*node).right, sentinel);
+            (subst, (*subst).right)
+        };
+
+        if subst == *root {
+            *root = temp;
+            (*temp).color = 0;
+            return;
+        }
+
+        let red = is_red(subst);
+
+        if subst == (*(*subst).parent).left {
+            (*(*subst).parent).left = temp;
+        } else {
+            (*(*subst).parent).right = temp;
+        }
+
+        if subst == node {
+            (*temp).parent = (*subst).parent;
+        } else {
+            if (*subst).parent == node {
+                (*temp).parent = subst;
+            } else {
+                (*temp).parent = (*subst).parent;
+            }
+
+            (*subst).left = (*node).left;
+            (*subst).right = (*node).right;
+            (*subst).parent = (*node).parent;
+            (*subst).color = (*node).color;
+
+            if node == *root {
+                *root = subst;
+            } else if node == (*(*node).parent).left {
+                (*(*node).parent).left = subst;
+            } else {
+                (*(*node).parent).right = subst;
+            }
+
+            if (*subst).left != sentinel {
+                (*(*subst).left).parent = subst;
+            }
+            if (*subst).right != sentinel {
+                (*(*subst).right).parent = subst;
+            }
+        }
+
+        if red {
+            return;
+        }
+
+        while temp != *root && !is_red(temp) {
+            let parent = (*temp).parent;
+
+            if temp == (*parent).left {
+                let mut w = (*parent).right;
+                if is_red(w) {
+                    (*w).color = 0;
+                    (*parent).color = 1;
+                    left_rotate(root, sentinel, parent);
+                    w = (*parent).right;
+                }
+
+                if !is_red((*w).left) && !is_red((*w).right) {
+                    (*w).color = 1;
+                    temp = parent;
+                } else {
+                    if !is_red((*w).right) {
+                        (*(*w).left).color = 0;
+                        (*w).color = 1;
+                        right_rotate(root, sentinel, w);
+                        w = (*parent).right;
+                    }
+                    (*w).color = (*parent).color;
+                    (*parent).color = 0;
+                    (*(*w).right).color = 0;
+                    left_rotate(root, sentinel, parent);
+                    temp = *root;
+                }
+            } else {
+                let mut w = (*parent).left;
+                if is_red(w) {
+                    (*w).color = 0;
+                    (*parent).color = 1;
+                    right_rotate(root, sentinel, parent);
+                    w = (*parent).left;
+                }
+
+                if !is_red((*w).left) && !is_red((*w).right) {
+                    (*w).color = 1;
+                    temp = parent;
+                } else {
+                    if !is_red((*w).left) {
+                        (*(*w).right).color = 0;
+                        (*w).color = 1;
+                        left_rotate(root, sentinel, w);
+                        w = (*parent).left;
+                    }
+                    (*w).color = (*parent).color;
+                    (*parent).color = 0;
+                    (*(*w).left).color = 0;
+                    right_rotate(root, sentinel, parent);
+                    temp = *root;
+                }
+            }
+        }
+
+        (*temp).color = 0;
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_rbtree_next(tree: *mut ngx_rbtree_t, mut node: NodePtr) -> NodePtr {
+        let sentinel = (*tree).sentinel;
+
+        if (*node).right != sentinel {
+            return min((*node).right, sentinel);
+        }
+
+        let root = (*tree).root;
+        loop {
+            let parent = (*node).parent;
+            if node == root {
+                return ptr::null_mut();
+            }
+            if node == (*parent).left {
+                return parent;
+            }
+            node = parent;
+        }
+    }
+
+    /// Key with the same hash for all values.
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Collide(u32);
+
+    impl Hash for Collide {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u8.hash(state)
+        }
+    }
+
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        let mut hasher = Fnv1a(0xcbf29ce484222325);
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn fnv() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn insert_get() {
+        let mut tree = RbTree::try_new_in(Global).unwrap();
+        assert!(tree.is_empty());
+
+        for i in 0..100u32 {
+            assert_eq!(tree.insert(i, i * 10), Ok(None));
+        }
+        assert_eq!(tree.len(), 100);
+
+        for i in 0..100u32 {
+            assert_eq!(tree.get(&i), Some(&(i * 10)));
+        }
+        assert_eq!(tree.get(&100), None);
+
+        // replace
+        assert_eq!(tree.insert(5, 0), Ok(Some(50)));
+        *tree.get_mut(&6).unwrap() += 1;
+        assert_eq!(tree.get(&5), Some(&0));
+        assert_eq!(tree.get(&6), Some(&61));
+        assert_eq!(tree.len(), 100);
+    }
+
+    #[test]
+    fn remove() {
+        let mut tree = RbTree::try_new_in(Global).unwrap();
+        for i in 0..100u32 {
+            tree.insert(i, i).unwrap();
+        }
+
+        for i in (0..100u32).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        assert_eq!(tree.remove(&0), None);
+        assert_eq!(tree.len(), 50);
+
+        for i in 0..100u32 {
+            assert_eq!(tree.get(&i).is_some(), i % 2 == 1);
+        }
+        assert_eq!(tree.iter().count(), 50);
+
+        for i in (1..100u32).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn iter_order() {
+        let mut tree = RbTree::try_new_in(Global).unwrap();
+        for i in (0..100u32).rev() {
+            tree.insert(i, ()).unwrap();
+        }
+
+        // ordered by the hash, then by the key
+        let keys: Vec<u32> = tree.iter().map(|(k, _)| *k).collect();
+        let mut expected: Vec<u32> = (0..100).collect();
+        expected.sort_by_key(|k| (hash_key(k), *k));
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn collisions() {
+        let mut tree = RbTree::try_new_in(Global).unwrap();
+        for i in [5, 3, 8, 1, 4, 7, 9, 2, 6, 0] {
+            tree.insert(Collide(i), i).unwrap();
+        }
+
+        let keys: Vec<u32> = tree.iter().map(|(k, _)| k.0).collect();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+
+        assert_eq!(tree.get(&Collide(7)), Some(&7));
+        assert_eq!(tree.remove(&Collide(3)), Some(3));
+        assert_eq!(tree.get(&Collide(3)), None);
+        assert_eq!(tree.insert(Collide(4), 40), Ok(Some(4)));
+        assert_eq!(tree.len(), 9);
+    }
+
+    #[test]
+    fn drop_values() {
+        let value = Rc::new(());
+
+        let mut tree = RbTree::try_new_in(Global).unwrap();
+        for i in 0..10u32 {
+            tree.insert(i, value.clone()).unwrap();
+        }
+        assert_eq!(Rc::strong_count(&value), 11);
+
+        drop(tree.remove(&0));
+        assert_eq!(Rc::strong_count(&value), 10);
+
+        tree.insert(1, value.clone()).unwrap();
+        assert_eq!(Rc::strong_count(&value), 10);
+
+        drop(tree);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,383 @@
+use core::ffi::c_void;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::{mem, slice};
+
+use crate::core::{LockedSlabPool, NgxList, NgxStr, Pool, SlabPool, Status};
+use crate::ffi::*;
+
+/// SharedZoneError - shared memory zone cannot be added to the configuration.
+#[derive(Debug)]
+pub enum SharedZoneError {
+    /// Memory allocation failed
+    Alloc,
+    /// Zone with the same name is already declared for a different use or with a different size
+    Conflict,
+    /// Zone with the same name is already used by the module
+    Duplicate,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SharedZoneError {}
+
+impl fmt::Display for SharedZoneError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SharedZoneError::Alloc => "memory allocation failed".fmt(fmt),
+            SharedZoneError::Conflict => "conflicting zone".fmt(fmt),
+            SharedZoneError::Duplicate => "duplicate zone".fmt(fmt),
+        }
+    }
+}
+
+/// Per-cycle state of a zone, stored in the `data` field of [`ngx_shm_zone_t`].
+struct ZoneContext<T> {
+    data: *mut T,
+}
+
+/// Shared memory zone holding a value of type `T`, accessible from all worker processes.
+///
+/// The value is allocated from the slab pool of the zone and initialized with `T::default()` when
+/// the zone is created. On configuration reload, the value is reused if the zone keeps the same name
+/// and size.
+///
+/// The value is placed in shared memory and never dropped. It must not contain pointers to the
+/// process memory, such as `Box` or `Vec` allocated from the global allocator.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#shared_memory>
+pub struct SharedZone<T> {
+    zone: NonNull<ngx_shm_zone_t>,
+    _p: PhantomData<T>,
+}
+
+impl<T> Clone for SharedZone<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SharedZone<T> {}
+
+impl<T: Default> SharedZone<T> {
+    /// Adds a shared memory zone to the configuration, e.g. from a directive handler.
+    ///
+    /// The zones with the same `name` are shared between the modules only if they use the same `tag`,
+    /// usually the address of the module. The size of `0` means that the zone is only referenced by the
+    /// directive and should be declared elsewhere with a non-zero size; it is an error to declare a zone
+    /// with a non-zero size twice.
+    ///
+    /// Returns [`SharedZoneError::Conflict`] if the zone is already declared with a different `tag` or
+    /// size; the reason is logged by nginx.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid `ngx_conf_t` of the configuration being parsed.
+    pub unsafe fn add(
+        cf: *mut ngx_conf_t,
+        name: &ngx_str_t,
+        size: usize,
+        tag: &ngx_module_t,
+    ) -> Result<Self, SharedZoneError> {
+        let mut name = *name;
+        let zone = ngx_shared_memory_add(cf, &mut name, size, tag as *const _ as *mut c_void);
+        let Some(mut zone) = NonNull::new(zone) else {
+            // a conflicting zone is not replaced
+            let zones = NgxList::<ngx_shm_zone_t>::from_ngx_list(&(*(*cf).cycle).shared_memory);
+            if zones.iter().any(|z| z.shm.name.as_bytes() == name.as_bytes()) {
+                return Err(SharedZoneError::Conflict);
+            }
+            return Err(SharedZoneError::Alloc);
+        };
+
+        let z = zone.as_mut();
+        if size == 0 {
+            return Ok(Self { zone, _p: PhantomData });
+        }
+
+        if !z.data.is_null() {
+            return Err(SharedZoneError::Duplicate);
+        }
+
+        let ctx = Pool::from_ngx_conf(&*cf).calloc(mem::size_of::<ZoneContext<T>>()) as *mut ZoneContext<T>;
+        if ctx.is_null() {
+            return Err(SharedZoneError::Alloc);
+        }
+
+        z.init = Some(shared_zone_init::<T>);
+        z.data = ctx.cast();
+
+        Ok(Self { zone, _p: PhantomData })
+    }
+}
+
+impl<T> SharedZone<T> {
+    /// Name of the zone.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.zone.as_ref().shm.name) }
+    }
+
+    /// Size of the zone in bytes.
+    pub fn size(&self) -> usize {
+        unsafe { self.zone.as_ref().shm.size }
+    }
+
+    /// Locks the zone mutex, blocking the current process until it is able to do so.
+    ///
+    /// Returns `None` if the zone is not initialized yet, i.e. during configuration parsing.
+    pub fn lock(&self) -> Option<SharedZoneGuard<'_, T>> {
+        let (mutex, shpool) = self.mutex()?;
+        Some(SharedZoneGuard {
+            // SAFETY: the lock and the value are stored in the zone and live as long as the zone
+            guard: unsafe { mutex.lock_unbound() },
+            shpool,
+        })
+    }
+
+    /// Attempts to lock the zone mutex without blocking.
+    ///
+    /// Returns `None` if the zone is not initialized yet or the mutex is held by another process.
+    pub fn try_lock(&self) -> Option<SharedZoneGuard<'_, T>> {
+        let (mutex, shpool) = self.mutex()?;
+        Some(SharedZoneGuard {
+            // SAFETY: the lock and the value are stored in the zone and live as long as the zone
+            guard: unsafe { mutex.try_lock_unbound()? },
+            shpool,
+        })
+    }
+
+    /// Slab pool of the zone.
+    ///
+    /// The pool can be used to allocate additional shared memory.
+    pub fn slab_pool(&self) -> &SlabPool {
+        // SAFETY: the zone memory is initialized as a slab pool before use
+        unsafe { SlabPool::from_ngx_slab_pool(self.zone.as_ref().shm.addr.cast()) }
+    }
+
+    /// Returns the inner data structure that the SharedZone object is wrapping.
+    pub fn get_inner(&self) -> &ngx_shm_zone_t {
+        unsafe { self.zone.as_ref() }
+    }
+
+    fn mutex(&self) -> Option<(ShmMutex<'_, T>, *mut ngx_slab_pool_t)> {
+        unsafe {
+            let ctx = self.zone.as_ref().data as *const ZoneContext<T>;
+            let data = ctx.as_ref()?.data;
+            if data.is_null() {
+                return None;
+            }
+            let shpool = self.slab_pool().as_ngx_slab_pool();
+            Some((ShmMutex::from_raw(&mut (*shpool).mutex, data), shpool))
+        }
+    }
+}
+
+/// Zone initialization callback, invoked for each zone in the new cycle.
+///
+/// `data` is the context of the same zone in the previous cycle, if any.
+unsafe extern "C" fn shared_zone_init<T: Default>(zone: *mut ngx_shm_zone_t, data: *mut c_void) -> ngx_int_t {
+    let ctx = (*zone).data as *mut ZoneContext<T>;
+    let octx = data as *mut ZoneContext<T>;
+
+    if !octx.is_null() {
+        (*ctx).data = (*octx).data;
+        return Status::NGX_OK.into();
+    }
+
+    let shpool = (*zone).shm.addr as *mut ngx_slab_pool_t;
+
+    // the zone was created by the master process (Windows)
+    if (*zone).shm.exists() != 0 {
+        (*ctx).data = (*shpool).data.cast();
+        return Status::NGX_OK.into();
+    }
+
+    let value = ngx_slab_alloc(shpool, mem::size_of::<T>()) as *mut T;
+    if value.is_null() {
+        return Status::NGX_ERROR.into();
+    }
+    ptr::write(value, T::default());
+
+    (*ctx).data = value;
+    (*shpool).data = value.cast();
+
+    // used in the "no memory" error messages of the slab allocator
+    let name = (*zone).shm.name.as_bytes();
+    let prefix = b" in zone \"";
+    let len = prefix.len() + name.len() + 2;
+    let log_ctx = ngx_slab_alloc(shpool, len) as *mut u8;
+    if !log_ctx.is_null() {
+        let buf = slice::from_raw_parts_mut(log_ctx, len);
+        buf[..prefix.len()].copy_from_slice(prefix);
+        buf[prefix.len()..len - 2].copy_from_slice(name);
+        buf[len - 2..].copy_from_slice(b"\"\0");
+        (*shpool).log_ctx = log_ctx;
+    }
+
+    Status::NGX_OK.into()
+}
+
+/// Locked access to the value of a [`SharedZone`].
+///
+/// The zone mutex is released when the guard is dropped.
+pub struct SharedZoneGuard<'a, T> {
+    guard: ShmMutexGuard<'a, T>,
+    shpool: *mut ngx_slab_pool_t,
+}
+
+impl<T> SharedZoneGuard<'_, T> {
+    /// Slab pool of the zone, for allocations with the mutex held.
+    pub fn slab_pool(&self) -> &LockedSlabPool {
+        // SAFETY: the mutex is held for the lifetime of the guard
+        unsafe { SlabPool::from_ngx_slab_pool(self.shpool).assume_locked() }
+    }
+}
+
+impl<T> Deref for SharedZoneGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SharedZoneGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// Cross-process mutex protecting a value in shared memory.
+///
+/// Wraps an [`ngx_shmtx_t`] lock, such as the mutex of a slab pool or a lock created in a
+/// shared memory zone with [`ShmMutex::create`].
+///
+/// If a worker process terminates abnormally, the master process releases the slab pool mutexes
+/// held by the process. Other locks can be released with [`ShmMutex::force_unlock`].
+pub struct ShmMutex<'a, T> {
+    mtx: NonNull<ngx_shmtx_t>,
+    data: NonNull<T>,
+    _p: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> ShmMutex<'a, T> {
+    /// Creates a mutex from an initialized [`ngx_shmtx_t`] and the value it protects.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided valid non-null pointers to an initialized lock and a value that is
+    /// only accessed with the lock held, both valid for the lifetime `'a`.
+    pub unsafe fn from_raw(mtx: *mut ngx_shmtx_t, data: *mut T) -> Self {
+        Self {
+            mtx: NonNull::new(mtx).expect("non-null mutex"),
+            data: NonNull::new(data).expect("non-null data"),
+            _p: PhantomData,
+        }
+    }
+
+    /// Initializes a lock stored in shared memory at `addr`, protecting the value at `data`.
+    ///
+    /// The process-local state of the lock is kept in `mtx`. `name` is used for the lock file on
+    /// platforms without atomic operations.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided valid pointers to a process-local `ngx_shmtx_t`, a shared
+    /// `ngx_shmtx_sh_t` and a value in shared memory, all valid for the lifetime `'a`.
+    pub unsafe fn create(
+        mtx: *mut ngx_shmtx_t,
+        addr: *mut ngx_shmtx_sh_t,
+        name: &core::ffi::CStr,
+        data: *mut T,
+    ) -> Option<Self> {
+        if ngx_shmtx_create(mtx, addr, name.as_ptr() as *mut u_char) != Status::NGX_OK.into() {
+            return None;
+        }
+        Some(Self::from_raw(mtx, data))
+    }
+
+    /// Acquires the lock, blocking the current process until it is able to do so.
+    pub fn lock(&self) -> ShmMutexGuard<'_, T> {
+        // SAFETY: the guard borrows the mutex
+        unsafe { self.lock_unbound() }
+    }
+
+    /// Attempts to acquire the lock without blocking.
+    ///
+    /// Returns `None` if the lock is held by another process.
+    pub fn try_lock(&self) -> Option<ShmMutexGuard<'_, T>> {
+        // SAFETY: the guard borrows the mutex
+        unsafe { self.try_lock_unbound() }
+    }
+
+    /// Acquires the lock, returning a guard not bound to the lifetime of `self`.
+    ///
+    /// # Safety
+    ///
+    /// The caller ensures that the lock and the value outlive the guard and that no other guard
+    /// exists in the process.
+    unsafe fn lock_unbound<'b>(&self) -> ShmMutexGuard<'b, T> {
+        ngx_shmtx_lock(self.mtx.as_ptr());
+        ShmMutexGuard {
+            mtx: self.mtx,
+            data: self.data,
+            _p: PhantomData,
+        }
+    }
+
+    /// Attempts to acquire the lock, returning a guard not bound to the lifetime of `self`.
+    ///
+    /// # Safety
+    ///
+    /// See [`ShmMutex::lock_unbound`].
+    unsafe fn try_lock_unbound<'b>(&self) -> Option<ShmMutexGuard<'b, T>> {
+        if ngx_shmtx_trylock(self.mtx.as_ptr()) == 0 {
+            return None;
+        }
+        Some(ShmMutexGuard {
+            mtx: self.mtx,
+            data: self.data,
+            _p: PhantomData,
+        })
+    }
+
+    /// Releases the lock if it is held by the process with the specified `pid`, e.g. a crashed
+    /// worker process.
+    ///
+    /// Returns `true` if the lock was released.
+    pub fn force_unlock(&self, pid: ngx_pid_t) -> bool {
+        unsafe { ngx_shmtx_force_unlock(self.mtx.as_ptr(), pid) != 0 }
+    }
+}
+
+/// RAII guard of a locked [`ShmMutex`].
+///
+/// The lock is released when the guard is dropped, including the early returns and panics.
+pub struct ShmMutexGuard<'a, T> {
+    mtx: NonNull<ngx_shmtx_t>,
+    data: NonNull<T>,
+    _p: PhantomData<&'a mut T>,
+}
+
+impl<T> Deref for ShmMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the value is protected by the held lock
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T> DerefMut for ShmMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the value is protected by the held lock
+        unsafe { self.data.as_mut() }
+    }
+}
+
+impl<T> Drop for ShmMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ngx_shmtx_unlock(self.mtx.as_ptr()) };
+    }
+}
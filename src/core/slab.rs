@@ -0,0 +1,154 @@
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+use crate::allocator::{AllocError, Allocator, Layout};
+use crate::ffi::*;
+
+/// Wrapper struct for an [`ngx_slab_pool_t`], providing methods for allocating shared memory.
+///
+/// The methods of `SlabPool` acquire the pool mutex for each operation. Code already holding the
+/// mutex, e.g. through a [`SharedZoneGuard`](crate::core::SharedZoneGuard), must use the
+/// [`LockedSlabPool`] instead.
+///
+/// Allocation failures are reported to the error log with the log context of the zone, unless
+/// disabled with [`SlabPool::set_log_nomem`].
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#shared_memory>
+#[repr(transparent)]
+pub struct SlabPool(UnsafeCell<ngx_slab_pool_t>);
+
+impl SlabPool {
+    /// Creates a `SlabPool` reference from an `ngx_slab_pool_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid initialized `ngx_slab_pool_t` pointer is provided, and that
+    /// the shared memory zone outlives the lifetime `'a`.
+    /// A null argument will cause an assertion failure and panic.
+    pub unsafe fn from_ngx_slab_pool<'a>(pool: *mut ngx_slab_pool_t) -> &'a SlabPool {
+        assert!(!pool.is_null());
+        &*pool.cast::<SlabPool>()
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_slab_pool_t`.
+    pub fn as_ngx_slab_pool(&self) -> *mut ngx_slab_pool_t {
+        self.0.get()
+    }
+
+    /// Allocates memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or a null pointer if the zone is out of memory.
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_alloc(self.as_ngx_slab_pool(), size) }
+    }
+
+    /// Allocates zeroed memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or a null pointer if the zone is out of memory.
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_calloc(self.as_ngx_slab_pool(), size) }
+    }
+
+    /// Releases the memory allocated from the pool.
+    ///
+    /// # Safety
+    /// The pointer must be allocated from this pool and not used after the call.
+    pub unsafe fn free(&self, p: *mut c_void) {
+        ngx_slab_free(self.as_ngx_slab_pool(), p)
+    }
+
+    /// Enables or disables logging of the allocation failures.
+    ///
+    /// Useful for the zones where running out of memory is expected and handled, e.g. by evicting
+    /// old entries.
+    pub fn set_log_nomem(&self, log: bool) {
+        unsafe { (*self.as_ngx_slab_pool()).set_log_nomem(if log { 1 } else { 0 }) };
+    }
+
+    /// Returns the pool for use with the mutex already held.
+    ///
+    /// # Safety
+    /// The caller must hold the pool mutex for the lifetime of the returned reference.
+    pub unsafe fn assume_locked(&self) -> &LockedSlabPool {
+        &*(self as *const SlabPool).cast::<LockedSlabPool>()
+    }
+}
+
+/// A [`SlabPool`] with the mutex held by the current process.
+///
+/// The allocation methods use the `_locked` variants of the slab functions.
+#[repr(transparent)]
+pub struct LockedSlabPool(SlabPool);
+
+impl LockedSlabPool {
+    /// Allocates memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or a null pointer if the zone is out of memory.
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_alloc_locked(self.as_ngx_slab_pool(), size) }
+    }
+
+    /// Allocates zeroed memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or a null pointer if the zone is out of memory.
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_calloc_locked(self.as_ngx_slab_pool(), size) }
+    }
+
+    /// Releases the memory allocated from the pool.
+    ///
+    /// # Safety
+    /// The pointer must be allocated from this pool and not used after the call.
+    pub unsafe fn free(&self, p: *mut c_void) {
+        ngx_slab_free_locked(self.as_ngx_slab_pool(), p)
+    }
+}
+
+impl Deref for LockedSlabPool {
+    type Target = SlabPool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Returns the allocation size guaranteeing the requested alignment.
+///
+/// The slab allocator aligns the small allocations to the size rounded up to a power of two, and the
+/// large allocations to the page size.
+fn slab_size(layout: Layout) -> Result<usize, AllocError> {
+    if layout.align() > unsafe { ngx_pagesize } {
+        return Err(AllocError);
+    }
+    Ok(layout.size().max(layout.align()))
+}
+
+/// Allocator API implementation for the shared memory zone.
+///
+/// The allocation failures of the infallible collection methods abort the process; `try_reserve` and
+/// similar methods should be used for the graceful handling of the zone exhaustion.
+unsafe impl Allocator for SlabPool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let size = slab_size(layout)?;
+        let p = NonNull::new(self.alloc(size).cast::<u8>()).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(p, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.free(ptr.as_ptr().cast())
+    }
+}
+
+/// Allocator API implementation for the shared memory zone with the mutex held.
+unsafe impl Allocator for LockedSlabPool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let size = slab_size(layout)?;
+        let p = NonNull::new(self.alloc(size).cast::<u8>()).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(p, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.free(ptr.as_ptr().cast())
+    }
+}
@@ -1,12 +1,20 @@
 use core::slice;
 use core::str::{self, Utf8Error};
+#[cfg(feature = "alloc")]
+use core::{fmt, mem, ops};
 
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::{borrow::Cow, string::String};
 #[cfg(feature = "std")]
 use std::{borrow::Cow, string::String};
 
+#[cfg(feature = "alloc")]
+use crate::allocator::{AllocError, Vec};
+#[cfg(feature = "alloc")]
+use crate::core::Pool;
 use crate::ffi::{ngx_str_t, u_char};
+#[cfg(feature = "alloc")]
+use crate::ffi::{ngx_strlow, ngx_strncasecmp};
 
 /// Static string initializer for [`ngx_str_t`].
 ///
@@ -91,3 +99,223 @@ impl Default for &NgxStr {
         unsafe { NgxStr::from_ngx_str(ngx_str_t::default()) }
     }
 }
+
+/// Owned [Nginx string] allocated from a [`Pool`].
+///
+/// The string is not required to be valid UTF-8. The memory is released when the string is dropped
+/// or, at the latest, when the pool is destroyed.
+///
+/// [Nginx string]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
+#[cfg(feature = "alloc")]
+pub struct NgxString<'a>(Vec<u8, &'a Pool>);
+
+#[cfg(feature = "alloc")]
+impl<'a> NgxString<'a> {
+    /// Creates an empty [`NgxString`] in the pool.
+    pub fn new_in(pool: &'a Pool) -> Self {
+        Self(Vec::new_in(pool))
+    }
+
+    /// Creates an [`NgxString`] in the pool with a copy of `bytes`.
+    pub fn try_from_bytes_in(bytes: impl AsRef<[u8]>, pool: &'a Pool) -> Result<Self, AllocError> {
+        let mut s = Self::new_in(pool);
+        s.append(bytes)?;
+        Ok(s)
+    }
+
+    /// Appends a copy of `bytes` to the end of the string.
+    pub fn append(&mut self, bytes: impl AsRef<[u8]>) -> Result<(), AllocError> {
+        let bytes = bytes.as_ref();
+        self.0.try_reserve(bytes.len()).map_err(|_| AllocError)?;
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Access the [`NgxString`] as an [`NgxStr`].
+    pub fn as_ngx_str(&self) -> &NgxStr {
+        self.0.as_slice().into()
+    }
+
+    /// Access the [`NgxString`] as a byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    /// Returns the length of the string in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the string has a length of 0.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Converts the string to ASCII lower case in place, using `ngx_strlow`.
+    pub fn make_lowercase(&mut self) {
+        let p = self.0.as_mut_ptr();
+        // SAFETY: `ngx_strlow` processes the string byte by byte and supports `dst == src`
+        unsafe { ngx_strlow(p, p, self.0.len()) };
+    }
+
+    /// Checks that the string is an ASCII case-insensitive match for `other`, using `ngx_strncasecmp`.
+    pub fn eq_ignore_case(&self, other: impl AsRef<[u8]>) -> bool {
+        let other = other.as_ref();
+        other.len() == self.len() && self.starts_with_ignore_case(other)
+    }
+
+    /// Checks that the string starts with an ASCII case-insensitive match for `prefix`, using
+    /// `ngx_strncasecmp`.
+    pub fn starts_with_ignore_case(&self, prefix: impl AsRef<[u8]>) -> bool {
+        let prefix = prefix.as_ref();
+        if prefix.len() > self.len() {
+            return false;
+        }
+        // SAFETY: both strings have at least `prefix.len()` bytes and are not modified by the call
+        unsafe {
+            ngx_strncasecmp(
+                self.0.as_ptr() as *mut u_char,
+                prefix.as_ptr() as *mut u_char,
+                prefix.len(),
+            ) == 0
+        }
+    }
+
+    /// Returns an [`ngx_str_t`] pointing to the string data, without copying.
+    ///
+    /// The returned value is valid until the string is modified or dropped.
+    pub fn as_ngx_str_t(&self) -> ngx_str_t {
+        ngx_str_t {
+            len: self.0.len(),
+            data: self.0.as_ptr() as *mut u_char,
+        }
+    }
+
+    /// Converts the string into an [`ngx_str_t`] without copying.
+    ///
+    /// The string data remains allocated until the pool is destroyed.
+    pub fn into_ngx_str_t(self) -> ngx_str_t {
+        let s = mem::ManuallyDrop::new(self);
+        s.as_ngx_str_t()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ops::Deref for NgxString<'_> {
+    type Target = NgxStr;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_ngx_str()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl AsRef<[u8]> for NgxString<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PartialEq<[u8]> for NgxString<'_> {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_bytes() == other
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PartialEq<str> for NgxString<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PartialEq for NgxString<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Write for NgxString<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s).map_err(|_| fmt::Error)
+    }
+}
+
+/// Formats the string as UTF-8, with the invalid bytes escaped as `\xNN`.
+#[cfg(feature = "alloc")]
+impl fmt::Display for NgxString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.as_bytes().utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            for b in chunk.invalid() {
+                write!(f, "\\x{b:02x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for NgxString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.as_bytes().escape_ascii())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::fmt::Write;
+
+    use std::format;
+
+    use super::*;
+    use crate::core::pool::tests::TestPool;
+
+    #[test]
+    fn growth() {
+        let pool = TestPool::new();
+        let mut s = NgxString::new_in(&pool);
+        assert!(s.is_empty());
+
+        for i in 0..100 {
+            s.append(b"0123456789").unwrap();
+            assert_eq!(s.len(), (i + 1) * 10);
+        }
+        assert!(s.as_bytes().chunks(10).all(|x| x == b"0123456789"));
+
+        write!(s, "{}", 42).unwrap();
+        assert!(s.as_bytes().ends_with(b"678942"));
+
+        let t = NgxString::try_from_bytes_in(s.as_bytes(), &pool).unwrap();
+        assert!(t == s);
+
+        let ns = s.as_ngx_str_t();
+        assert_eq!(ns.len, 1002);
+        assert_eq!(ns.data.cast_const(), s.as_bytes().as_ptr());
+    }
+
+    #[test]
+    fn utf8() {
+        let pool = TestPool::new();
+
+        let s = NgxString::try_from_bytes_in("caf\u{e9}", &pool).unwrap();
+        assert_eq!(s.to_str(), Ok("caf\u{e9}"));
+        assert_eq!(s.to_string_lossy(), "caf\u{e9}");
+
+        let s = NgxString::try_from_bytes_in(b"caf\xe9", &pool).unwrap();
+        assert!(s.to_str().is_err());
+        assert_eq!(s.to_string_lossy(), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn format() {
+        let pool = TestPool::new();
+
+        let s = NgxString::try_from_bytes_in(b"\"caf\xc3\xa9\" \xff", &pool).unwrap();
+        assert_eq!(format!("{s}"), "\"caf\u{e9}\" \\xff");
+        assert_eq!(format!("{s:?}"), r#""\"caf\xc3\xa9\" \xff""#);
+    }
+}
@@ -0,0 +1,250 @@
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr::{self, NonNull};
+
+use std::boxed::Box;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use crate::core::Status;
+use crate::ffi::*;
+
+/// SpawnError - the task cannot be posted to the thread pool.
+///
+/// The queue of the thread pool is full, as limited by the `max_queue` parameter of the
+/// `thread_pool` directive.
+#[derive(Debug)]
+pub struct SpawnError;
+
+impl std::error::Error for SpawnError {}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        "ngx_thread_task_post() failed".fmt(fmt)
+    }
+}
+
+/// Wrapper struct for an [`ngx_thread_pool_t`], a pool of threads configured with the
+/// `thread_pool` directive.
+///
+/// The pools are created on the configuration load and are shared by all the modules. A module
+/// references a pool by name with [`ThreadPool::add`] when parsing the configuration, which also
+/// ensures that the pool is configured, and keeps the returned handle for the request processing.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadPool(NonNull<ngx_thread_pool_t>);
+
+impl ThreadPool {
+    /// Creates a new `ThreadPool` from an [`ngx_thread_pool_t`] pointer.
+    ///
+    /// # Safety
+    /// The caller has provided a valid non-null pointer to an `ngx_thread_pool_t`.
+    pub unsafe fn from_ngx_thread_pool(tp: *mut ngx_thread_pool_t) -> Self {
+        Self(NonNull::new(tp).expect("non-null thread pool"))
+    }
+
+    /// Adds a reference to the thread pool `name`, or to the `default` pool if `name` is `None`.
+    ///
+    /// The pool is created if it is not configured yet. The configuration is rejected with the
+    /// "unknown thread pool" error if a referenced pool other than `default` is never configured.
+    ///
+    /// # Safety
+    /// Must be called when parsing the configuration, with a valid `cf`.
+    pub unsafe fn add(cf: *mut ngx_conf_t, name: Option<&ngx_str_t>) -> Option<Self> {
+        let name = name.map_or(ptr::null_mut(), |x| ptr::from_ref(x).cast_mut());
+        NonNull::new(ngx_thread_pool_add(cf, name)).map(Self)
+    }
+
+    /// Returns the thread pool `name` of the running configuration, if configured.
+    ///
+    /// Only the pools referenced at the configuration time, with [`ThreadPool::add`], the
+    /// `thread_pool` directive or the other modules, are available.
+    pub fn get(name: &ngx_str_t) -> Option<Self> {
+        let tp = unsafe { ngx_thread_pool_get(ngx_cycle, ptr::from_ref(name).cast_mut()) };
+        NonNull::new(tp).map(Self)
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_thread_pool_t`.
+    pub fn as_ngx_thread_pool(&self) -> *mut ngx_thread_pool_t {
+        self.0.as_ptr()
+    }
+
+    /// Runs `work` on a thread of the pool, and then `completion` with its result on the worker
+    /// thread.
+    ///
+    /// The closure passed to `completion` receives the panic payload if `work` panics, as
+    /// [`std::thread::JoinHandle::join`] does. The objects referenced from `completion`, such as a
+    /// request, must outlive the task, e.g. by incrementing the request reference count.
+    ///
+    /// The tasks still running when the worker process exits are not completed.
+    ///
+    /// Must be called on the worker thread.
+    pub fn spawn<F, R, C>(&self, work: F, completion: C) -> Result<(), SpawnError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+        C: FnOnce(thread::Result<R>) + 'static,
+    {
+        let task = Box::into_raw(Box::new(ThreadTask::<F, R, C> {
+            task: unsafe { core::mem::zeroed() },
+            work: Some(work),
+            result: None,
+            completion: Some(completion),
+        }));
+
+        unsafe {
+            let t = ptr::addr_of_mut!((*task).task);
+            (*t).ctx = task.cast();
+            (*t).handler = Some(thread_handler::<F, R, C>);
+            (*t).event.data = task.cast();
+            (*t).event.handler = Some(completion_handler::<F, R, C>);
+            (*t).event.log = (*ngx_cycle).log;
+
+            if ngx_thread_task_post(self.0.as_ptr(), t) != Status::NGX_OK.into() {
+                drop(Box::from_raw(task));
+                return Err(SpawnError);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Thread task with the closures and the result of the work.
+///
+/// `work` is only accessed by the pool thread while the task is queued or running, the other
+/// fields are only accessed on the worker thread.
+#[repr(C)]
+struct ThreadTask<F, R, C> {
+    task: ngx_thread_task_t,
+    work: Option<F>,
+    result: Option<thread::Result<R>>,
+    completion: Option<C>,
+}
+
+unsafe extern "C" fn thread_handler<F, R, C>(data: *mut c_void, _log: *mut ngx_log_t)
+where
+    F: FnOnce() -> R,
+{
+    let task = data.cast::<ThreadTask<F, R, C>>();
+    if let Some(work) = (*task).work.take() {
+        // the panics must not unwind into the nginx code
+        (*task).result = Some(panic::catch_unwind(AssertUnwindSafe(work)));
+    }
+}
+
+unsafe extern "C" fn completion_handler<F, R, C>(ev: *mut ngx_event_t)
+where
+    C: FnOnce(thread::Result<R>),
+{
+    let task = Box::from_raw((*ev).data.cast::<ThreadTask<F, R, C>>());
+    let ThreadTask { result, completion, .. } = *task;
+
+    if let (Some(result), Some(completion)) = (result, completion) {
+        completion(result);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::{Cell, RefCell, UnsafeCell};
+    use core::mem;
+
+    use std::rc::Rc;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+
+    // the thread pool, running the tasks on a new thread each and collecting the completion events
+    // to process them later on the current thread, as the worker does after ngx_notify()
+
+    std::thread_local! {
+        static COMPLETED: RefCell<Vec<*mut ngx_event_t>> = const { RefCell::new(Vec::new()) };
+        static QUEUE_FULL: Cell<bool> = const { Cell::new(false) };
+    }
+
+    struct Cycle(UnsafeCell<ngx_cycle_t>);
+
+    // SAFETY: the cycle is never modified
+    unsafe impl Sync for Cycle {}
+
+    static CYCLE: Cycle = Cycle(UnsafeCell::new(unsafe { mem::zeroed() }));
+
+    #[no_mangle]
+    static mut ngx_cycle: *mut ngx_cycle_t = CYCLE.0.get();
+
+    #[no_mangle]
+    unsafe extern "C" fn ngx_thread_task_post(_tp: *mut ngx_thread_pool_t, task: *mut ngx_thread_task_t) -> ngx_int_t {
+        if QUEUE_FULL.get() {
+            return Status::NGX_ERROR.into();
+        }
+
+        let (handler, ctx) = ((*task).handler.unwrap(), (*task).ctx as usize);
+        thread::spawn(move || handler(ctx as *mut c_void, ptr::null_mut()))
+            .join()
+            .unwrap();
+
+        COMPLETED.with(|c| c.borrow_mut().push(ptr::addr_of_mut!((*task).event)));
+        Status::NGX_OK.into()
+    }
+
+    /// Runs the completion handlers of the finished tasks.
+    fn complete() {
+        for ev in COMPLETED.take() {
+            unsafe { ((*ev).handler.unwrap())(ev) };
+        }
+    }
+
+    fn thread_pool() -> ThreadPool {
+        unsafe { ThreadPool::from_ngx_thread_pool(NonNull::dangling().as_ptr()) }
+    }
+
+    #[test]
+    fn spawn() {
+        let result = Rc::new(Cell::new(None));
+        let r = result.clone();
+
+        let worker = thread::current().id();
+        thread_pool()
+            .spawn(
+                move || (thread::current().id() != worker, 42),
+                move |x| r.set(Some(x.unwrap())),
+            )
+            .unwrap();
+
+        // the completion runs on the worker thread after the task is done
+        assert_eq!(result.get(), None);
+        complete();
+        assert_eq!(result.get(), Some((true, 42)));
+        assert_eq!(Rc::strong_count(&result), 1);
+    }
+
+    #[test]
+    fn spawn_panic() {
+        let result = Rc::new(RefCell::new(None));
+        let r = result.clone();
+
+        thread_pool()
+            .spawn(|| -> u32 { panic!("task failed") }, move |x| *r.borrow_mut() = Some(x))
+            .unwrap();
+        complete();
+
+        let payload = result.take().unwrap().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"task failed"));
+    }
+
+    #[test]
+    fn spawn_error() {
+        let guard = Rc::new(String::from("completion"));
+        let g = guard.clone();
+
+        QUEUE_FULL.set(true);
+        let res = thread_pool().spawn(|| (), move |_| drop(g));
+        QUEUE_FULL.set(false);
+
+        assert!(res.is_err());
+        // the closures are dropped without running
+        assert_eq!(Rc::strong_count(&guard), 1);
+        assert!(COMPLETED.with(|c| c.borrow().is_empty()));
+    }
+}
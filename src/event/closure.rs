@@ -0,0 +1,139 @@
+use core::ffi::c_void;
+use core::ptr::{self, NonNull};
+
+use crate::allocator::{Allocator, Layout};
+use crate::core::Pool;
+use crate::event::{del_timer, delete_posted_event};
+use crate::ffi::*;
+
+/// Event allocated from the pool, with the closure called by the event handler placed after the
+/// event.
+///
+/// The event is removed from the timers and the posted events and the closure is dropped when the
+/// pool is destroyed.
+pub(crate) struct ClosureEvent<F> {
+    event: ngx_event_t,
+    handler: F,
+}
+
+impl<F: FnMut() + 'static> ClosureEvent<F> {
+    /// Allocates the event from the pool, using the log of the pool.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub(crate) fn create(pool: &Pool, handler: F) -> Option<NonNull<ngx_event_t>> {
+        let data = Self::new_in(pool, handler, unsafe { (*pool.as_ngx_pool()).log })?.as_ptr();
+
+        unsafe {
+            let cln = ngx_pool_cleanup_add(pool.as_ngx_pool(), 0);
+            if cln.is_null() {
+                ptr::drop_in_place(ptr::addr_of_mut!((*data).handler));
+                return None;
+            }
+
+            (*cln).handler = Some(closure_event_cleanup::<F>);
+            (*cln).data = data.cast();
+
+            Some(NonNull::new_unchecked(ptr::addr_of_mut!((*data).event)))
+        }
+    }
+
+    fn new_in<A: Allocator>(alloc: &A, handler: F, log: *mut ngx_log_t) -> Option<NonNull<Self>> {
+        // `Pool` has an inherent `allocate` method with a different signature
+        let data = Allocator::allocate(alloc, Layout::new::<Self>()).ok()?.cast::<Self>();
+
+        unsafe {
+            let p = data.as_ptr();
+            ptr::write_bytes(ptr::addr_of_mut!((*p).event), 0, 1);
+            ptr::write(ptr::addr_of_mut!((*p).handler), handler);
+
+            let ev = &mut (*p).event;
+            ev.data = p.cast();
+            ev.handler = Some(closure_event_handler::<F>);
+            ev.log = log;
+        }
+
+        Some(data)
+    }
+}
+
+unsafe extern "C" fn closure_event_handler<F: FnMut()>(ev: *mut ngx_event_t) {
+    let data = (*ev).data.cast::<ClosureEvent<F>>();
+    (*ev).set_timedout(0);
+    ((*data).handler)();
+}
+
+unsafe extern "C" fn closure_event_cleanup<F>(data: *mut c_void) {
+    let data = data.cast::<ClosureEvent<F>>();
+    let ev = ptr::addr_of_mut!((*data).event);
+
+    if (*ev).timer_set() != 0 {
+        del_timer(ev);
+    }
+
+    if (*ev).posted() != 0 {
+        delete_posted_event(ev);
+    }
+
+    ptr::drop_in_place(ptr::addr_of_mut!((*data).handler));
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::cell::Cell;
+    use core::mem;
+
+    use allocator_api2::alloc::Global;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::core::Queue;
+    use crate::event::{post_event, PostedQueue};
+
+    // the event loop globals, as defined in src/event/ngx_event_timer.c and ngx_event_posted.c
+
+    #[no_mangle]
+    static mut ngx_event_timer_rbtree: ngx_rbtree_t = unsafe { mem::zeroed() };
+
+    #[no_mangle]
+    static mut ngx_posted_events: ngx_queue_t = unsafe { mem::zeroed() };
+
+    #[no_mangle]
+    static mut ngx_posted_next_events: ngx_queue_t = unsafe { mem::zeroed() };
+
+    unsafe fn free<F>(data: NonNull<ClosureEvent<F>>) {
+        closure_event_cleanup::<F>(data.as_ptr().cast());
+        Global.deallocate(data.cast(), Layout::new::<ClosureEvent<F>>());
+    }
+
+    #[test]
+    fn closure_event() {
+        let count = Rc::new(Cell::new(0));
+        let c = count.clone();
+
+        let data = ClosureEvent::new_in(&Global, move || c.set(c.get() + 1), ptr::null_mut()).unwrap();
+
+        unsafe {
+            let ev = ptr::addr_of_mut!((*data.as_ptr()).event);
+            assert_eq!((*ev).data, data.as_ptr().cast());
+
+            for _ in 0..2 {
+                (*ev).set_timedout(1);
+                ((*ev).handler.unwrap())(ev);
+                assert_eq!((*ev).timedout(), 0);
+            }
+            assert_eq!(count.get(), 2);
+
+            let posted = Queue::<ngx_event_t>::from_ngx_queue(ptr::addr_of_mut!(ngx_posted_events));
+            posted.init();
+
+            post_event(ev, PostedQueue::Current);
+            assert!(!posted.is_empty());
+
+            free(data);
+            assert!(posted.is_empty());
+        }
+
+        // the handler is dropped with the cleanup
+        assert_eq!(Rc::strong_count(&count), 1);
+    }
+}
@@ -0,0 +1,621 @@
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::future::Future;
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use std::boxed::Box;
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+use std::task::Wake;
+use std::thread::{self, ThreadId};
+
+use crate::event::{add_timer, del_timer, notify, Notifier};
+use crate::ffi::*;
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Interval of checking for the wake-ups from the other threads, in milliseconds, when the
+/// [`Notifier`] cannot be created.
+const FALLBACK_INTERVAL: ngx_msec_t = 50;
+
+/// Task of the executor: the future and the posted event polling it.
+///
+/// The future is only accessed on the worker thread that spawned the task; the wakers can be sent
+/// to the other threads and post the event through the [`Notifier`].
+struct Task {
+    event: UnsafeCell<ngx_event_t>,
+    future: RefCell<Option<LocalFuture>>,
+    /// The task is canceled while its future is polled.
+    canceled: Cell<bool>,
+    /// The task is waiting and counted in the tasks polling for the fallback wake-ups.
+    parked: Cell<bool>,
+    thread: ThreadId,
+    notifier: Option<Notifier>,
+}
+
+// SAFETY: the event and the future are only accessed on the thread that created the task
+unsafe impl Send for Task {}
+unsafe impl Sync for Task {}
+
+impl Task {
+    /// Posts the event polling the task. The posted event holds a reference to the task.
+    fn schedule(self: &Arc<Self>) {
+        let ev = self.event.get();
+        unsafe {
+            if (*ev).posted() == 0 {
+                (*ev).data = Arc::into_raw(self.clone()).cast_mut().cast();
+                sys::post(ev);
+            }
+        }
+    }
+
+    /// Removes the event from the posted events, releasing its reference to the task.
+    fn unschedule(&self) {
+        let ev = self.event.get();
+        unsafe {
+            if (*ev).posted() != 0 {
+                sys::delete(ev);
+                drop(Arc::from_raw((*ev).data.cast::<Task>()));
+                (*ev).data = ptr::null_mut();
+            }
+        }
+    }
+
+    fn cancel(&self) {
+        self.unschedule();
+        self.unpark();
+
+        let future = match self.future.try_borrow_mut() {
+            Ok(mut future) => future.take(),
+            // the future cancels itself, and is dropped once the poll returns
+            Err(_) => {
+                self.canceled.set(true);
+                None
+            }
+        };
+        drop(future);
+    }
+
+    fn park(&self) {
+        if !self.parked.replace(true) {
+            FALLBACK.with(|fallback| {
+                fallback.parked.set(fallback.parked.get() + 1);
+                fallback.arm();
+            });
+        }
+    }
+
+    fn unpark(&self) {
+        if self.parked.replace(false) {
+            let _ = FALLBACK.try_with(|fallback| fallback.parked.set(fallback.parked.get() - 1));
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if thread::current().id() == self.thread {
+            self.unpark();
+        } else if let Some(future) = self.future.get_mut().take() {
+            // the last waker can be dropped on another thread; the future must not be dropped there
+            core::mem::forget(future);
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if thread::current().id() == self.thread {
+            self.schedule();
+            return;
+        }
+
+        let task = self.clone();
+        let schedule = move || task.schedule();
+
+        match self.notifier {
+            // the task is scheduled after the next successful notification if this one fails
+            Some(notifier) => {
+                let _ = notifier.schedule(schedule);
+            }
+            // the task is scheduled when the worker checks for the wake-ups
+            None => notify::enqueue(schedule),
+        }
+    }
+}
+
+unsafe extern "C" fn task_handler(ev: *mut ngx_event_t) {
+    let task = Arc::from_raw((*ev).data.cast::<Task>());
+    (*ev).data = ptr::null_mut();
+
+    let waker = Waker::from(task.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let ready = match task.future.borrow_mut().as_mut() {
+        Some(f) => f.as_mut().poll(&mut cx).is_ready(),
+        None => return,
+    };
+
+    if ready || task.canceled.get() {
+        task.unschedule();
+        task.unpark();
+        let future = task.future.borrow_mut().take();
+        drop(future);
+    } else if task.notifier.is_none() && (*ev).posted() == 0 {
+        // the task is not woken yet and can be woken from another thread
+        task.park();
+    }
+}
+
+/// Polling for the wake-ups from the other threads, used when the [`Notifier`] cannot be created.
+///
+/// The timer runs while there are tasks waiting for a wake-up.
+struct Fallback {
+    event: UnsafeCell<ngx_event_t>,
+    parked: Cell<usize>,
+}
+
+std::thread_local! {
+    static FALLBACK: Fallback = const {
+        Fallback {
+            event: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+            parked: Cell::new(0),
+        }
+    };
+}
+
+impl Fallback {
+    fn arm(&self) {
+        let ev = self.event.get();
+        unsafe {
+            if (*ev).handler.is_none() {
+                (*ev).handler = Some(fallback_handler);
+                (*ev).log = sys::log();
+                // the pending timer does not delay the graceful shutdown
+                (*ev).set_cancelable(1);
+            }
+
+            if (*ev).timer_set() == 0 {
+                sys::add_timer(ev, FALLBACK_INTERVAL);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn fallback_handler(_ev: *mut ngx_event_t) {
+    notify::run_queued();
+
+    FALLBACK.with(|fallback| {
+        if fallback.parked.get() > 0 {
+            fallback.arm();
+        }
+    });
+}
+
+/// State shared by a spawned future and its [`JoinHandle`].
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+    finished: bool,
+}
+
+/// Spawns the future on the event loop of the current worker process.
+///
+/// The future is polled on the worker thread from a posted event, so it can access the NGINX
+/// objects without synchronization. The future must ensure that the objects outlive it: a request
+/// is kept with a [`RequestRef`](crate::http::RequestRef).
+///
+/// The futures waiting for the NGINX events are provided by [`sleep`], [`PeerConnection`],
+/// [`Resolver`], [`RequestRef::read_body`](crate::http::RequestRef::read_body) and
+/// [`RequestRef::subrequest`](crate::http::RequestRef::subrequest).
+///
+/// The future can be woken from the other threads. The event loop is notified with the
+/// [`Notifier`]; if it cannot be created, the worker checks for the wake-ups each 50 milliseconds
+/// while there are waiting tasks.
+///
+/// Returns a [`JoinHandle`] for awaiting the output. Dropping the handle detaches the task.
+///
+/// Must be called on the worker thread.
+///
+/// [`PeerConnection`]: crate::event::PeerConnection
+/// [`Resolver`]: crate::event::Resolver
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let state = Rc::new(RefCell::new(JoinState {
+        output: None,
+        waker: None,
+        finished: false,
+    }));
+
+    let shared = state.clone();
+    let future = async move {
+        let output = future.await;
+        let mut state = shared.borrow_mut();
+        state.output = Some(output);
+        state.finished = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    };
+
+    let task = Arc::new(Task {
+        event: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+        future: RefCell::new(Some(Box::pin(future))),
+        canceled: Cell::new(false),
+        parked: Cell::new(false),
+        thread: thread::current().id(),
+        notifier: sys::notifier(),
+    });
+
+    unsafe {
+        let ev = &mut *task.event.get();
+        ev.handler = Some(task_handler);
+        ev.log = sys::log();
+    }
+
+    task.schedule();
+
+    JoinHandle { task, state }
+}
+
+/// Handle for awaiting the output of a spawned future.
+pub struct JoinHandle<T> {
+    task: Arc<Task>,
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns `true` if the future has completed.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+
+    /// Cancels the task, dropping the future if it has not completed yet.
+    ///
+    /// If called from the task itself, the future is dropped when it returns from the poll.
+    pub fn cancel(self) {
+        self.task.cancel();
+    }
+
+    /// Returns a handle canceling the task, which does not keep the task alive.
+    pub(crate) fn canceler(&self) -> TaskCanceler {
+        TaskCanceler(Arc::downgrade(&self.task))
+    }
+}
+
+/// Handle canceling a task, created with [`JoinHandle::canceler`].
+pub(crate) struct TaskCanceler(Weak<Task>);
+
+impl TaskCanceler {
+    /// Cancels the task if it has not completed yet, as [`JoinHandle::cancel`] does.
+    pub(crate) fn cancel(self) {
+        if let Some(task) = self.0.upgrade() {
+            task.cancel();
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Timer event of a [`Sleep`] future.
+struct SleepEvent {
+    event: ngx_event_t,
+    waker: Option<Waker>,
+}
+
+/// Future completing after a delay, created with [`sleep`].
+pub struct Sleep {
+    event: Box<SleepEvent>,
+    delay: ngx_msec_t,
+    started: bool,
+}
+
+/// Returns a future completing after `delay`, using the NGINX timers.
+///
+/// Must be awaited on the worker thread, e.g. from a future passed to [`spawn`].
+pub fn sleep(delay: Duration) -> Sleep {
+    let mut event = Box::new(SleepEvent {
+        event: unsafe { core::mem::zeroed() },
+        waker: None,
+    });
+
+    event.event.handler = Some(sleep_handler);
+    event.event.log = unsafe { (*ngx_cycle).log };
+    // the pending timers do not delay the graceful shutdown
+    event.event.set_cancelable(1);
+
+    Sleep {
+        event,
+        delay: delay.as_millis().min(ngx_msec_t::MAX as u128 / 2) as ngx_msec_t,
+        started: false,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let ev = ptr::addr_of_mut!(this.event.event);
+
+        if !this.started {
+            this.started = true;
+            unsafe {
+                (*ev).data = ptr::addr_of_mut!(*this.event).cast();
+                add_timer(ev, this.delay);
+            }
+        } else if unsafe { (*ev).timer_set() } == 0 {
+            return Poll::Ready(());
+        }
+
+        this.event.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        let ev = ptr::addr_of_mut!(self.event.event);
+        unsafe {
+            if (*ev).timer_set() != 0 {
+                del_timer(ev);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn sleep_handler(ev: *mut ngx_event_t) {
+    let sleep = (*ev).data.cast::<SleepEvent>();
+    if let Some(waker) = (*sleep).waker.take() {
+        waker.wake();
+    }
+}
+
+/// Event loop operations used by the executor.
+#[cfg(not(test))]
+mod sys {
+    use crate::event::{add_timer as event_add_timer, delete_posted_event, post_event, Notifier, PostedQueue};
+    use crate::ffi::*;
+
+    pub unsafe fn post(ev: *mut ngx_event_t) {
+        post_event(ev, PostedQueue::Current);
+    }
+
+    pub unsafe fn delete(ev: *mut ngx_event_t) {
+        delete_posted_event(ev);
+    }
+
+    pub unsafe fn add_timer(ev: *mut ngx_event_t, timer: ngx_msec_t) {
+        event_add_timer(ev, timer);
+    }
+
+    pub fn log() -> *mut ngx_log_t {
+        unsafe { (*ngx_cycle).log }
+    }
+
+    pub fn notifier() -> Option<Notifier> {
+        Notifier::new()
+    }
+}
+
+/// Event loop of the tests, without notifications.
+#[cfg(test)]
+mod sys {
+    use core::cell::{Cell, RefCell};
+    use core::ptr;
+
+    use std::collections::VecDeque;
+
+    use crate::event::Notifier;
+    use crate::ffi::*;
+
+    std::thread_local! {
+        static POSTED: RefCell<VecDeque<*mut ngx_event_t>> = const { RefCell::new(VecDeque::new()) };
+        static TIMER: Cell<*mut ngx_event_t> = const { Cell::new(ptr::null_mut()) };
+    }
+
+    pub unsafe fn post(ev: *mut ngx_event_t) {
+        (*ev).set_posted(1);
+        POSTED.with(|q| q.borrow_mut().push_back(ev));
+    }
+
+    pub unsafe fn delete(ev: *mut ngx_event_t) {
+        (*ev).set_posted(0);
+        POSTED.with(|q| q.borrow_mut().retain(|x| *x != ev));
+    }
+
+    pub unsafe fn add_timer(ev: *mut ngx_event_t, _timer: ngx_msec_t) {
+        (*ev).set_timer_set(1);
+        TIMER.with(|t| t.set(ev));
+    }
+
+    pub fn log() -> *mut ngx_log_t {
+        ptr::null_mut()
+    }
+
+    pub fn notifier() -> Option<Notifier> {
+        None
+    }
+
+    /// Processes the posted events, as `ngx_event_process_posted` does.
+    pub fn run_posted() {
+        while let Some(ev) = POSTED.with(|q| q.borrow_mut().pop_front()) {
+            unsafe {
+                (*ev).set_posted(0);
+                ((*ev).handler.unwrap())(ev);
+            }
+        }
+    }
+
+    /// Expires the timer, returning `false` if no timer is set.
+    pub fn expire_timer() -> bool {
+        let ev = TIMER.with(|t| t.replace(ptr::null_mut()));
+        if ev.is_null() {
+            return false;
+        }
+        unsafe {
+            (*ev).set_timer_set(0);
+            ((*ev).handler.unwrap())(ev);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::poll_fn;
+
+    use std::sync::Mutex;
+
+    use super::sys::{expire_timer, run_posted};
+    use super::*;
+
+    /// Yields to the other tasks once, waking itself.
+    async fn yield_now() {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if yielded {
+                return Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[test]
+    fn spawn_join() {
+        let out = Rc::new(Cell::new(0));
+
+        let o = out.clone();
+        spawn(async move {
+            let inner = spawn(async {
+                yield_now().await;
+                1
+            });
+            o.set(inner.await + 1);
+        });
+        assert_eq!(out.get(), 0);
+
+        run_posted();
+        assert_eq!(out.get(), 2);
+    }
+
+    #[test]
+    fn yield_wake() {
+        let polls = Rc::new(Cell::new(0));
+
+        let p = polls.clone();
+        let handle = spawn(async move {
+            for _ in 0..3 {
+                p.set(p.get() + 1);
+                yield_now().await;
+            }
+        });
+
+        run_posted();
+        assert_eq!(polls.get(), 3);
+        assert!(handle.is_finished());
+        assert!(!expire_timer());
+    }
+
+    #[test]
+    fn cancel() {
+        let guard = Rc::new(());
+
+        let g = guard.clone();
+        let handle = spawn(async move {
+            let _g = g;
+            unreachable!();
+        });
+        assert_eq!(Rc::strong_count(&guard), 2);
+
+        handle.cancel();
+        assert_eq!(Rc::strong_count(&guard), 1);
+
+        run_posted();
+    }
+
+    #[test]
+    fn cancel_self() {
+        let guard = Rc::new(());
+        let slot: Rc<RefCell<Option<JoinHandle<()>>>> = Rc::default();
+
+        let g = guard.clone();
+        let s = slot.clone();
+        let handle = spawn(async move {
+            let _g = g;
+            let handle = s.borrow_mut().take().unwrap();
+            handle.cancel();
+            yield_now().await;
+            unreachable!();
+        });
+        *slot.borrow_mut() = Some(handle);
+
+        run_posted();
+        assert_eq!(Rc::strong_count(&guard), 1);
+    }
+
+    #[test]
+    fn wake_from_thread() {
+        let waker: Arc<Mutex<Option<Waker>>> = Arc::default();
+        let done = Rc::new(Cell::new(false));
+
+        let w = waker.clone();
+        let d = done.clone();
+        spawn(async move {
+            let mut woken = false;
+            poll_fn(|cx| {
+                if woken {
+                    return Poll::Ready(());
+                }
+                woken = true;
+                *w.lock().unwrap() = Some(cx.waker().clone());
+                Poll::Pending
+            })
+            .await;
+            d.set(true);
+        });
+
+        run_posted();
+        assert!(!done.get());
+
+        let w = waker.clone();
+        thread::spawn(move || w.lock().unwrap().take().unwrap().wake())
+            .join()
+            .unwrap();
+
+        // the wake-up is processed with the fallback timer
+        run_posted();
+        assert!(!done.get());
+        assert!(expire_timer());
+
+        run_posted();
+        assert!(done.get());
+
+        // the fallback timer stops with no waiting tasks
+        assert!(expire_timer());
+        assert!(!expire_timer());
+    }
+}
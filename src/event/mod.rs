@@ -0,0 +1,22 @@
+mod closure;
+#[cfg(feature = "std")]
+mod executor;
+#[cfg(feature = "std")]
+mod notify;
+#[cfg(feature = "std")]
+mod peer;
+mod posted;
+#[cfg(feature = "std")]
+mod resolver;
+mod timer;
+
+#[cfg(feature = "std")]
+pub use executor::*;
+#[cfg(feature = "std")]
+pub use notify::*;
+#[cfg(feature = "std")]
+pub use peer::*;
+pub use posted::*;
+#[cfg(feature = "std")]
+pub use resolver::*;
+pub use timer::*;
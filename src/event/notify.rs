@@ -0,0 +1,177 @@
+use core::fmt;
+
+use std::boxed::Box;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::{Mutex, OnceLock};
+use std::vec::Vec;
+
+use crate::core::Status;
+use crate::ffi::*;
+
+/// NotifyError - the event loop cannot be notified.
+#[derive(Debug)]
+pub struct NotifyError;
+
+impl std::error::Error for NotifyError {}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        "event loop notification failed".fmt(fmt)
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Closures scheduled from the other threads, and whether the worker is already notified.
+struct NotifyQueue {
+    tasks: Vec<Task>,
+    notified: bool,
+}
+
+static QUEUE: Mutex<NotifyQueue> = Mutex::new(NotifyQueue {
+    tasks: Vec::new(),
+    notified: false,
+});
+
+/// Socket pair notifying the worker process: the other threads write to the first socket, and the
+/// worker reads from the second one with a connection registered in the event loop.
+static CHANNEL: OnceLock<(UnixStream, UnixStream)> = OnceLock::new();
+
+/// Thread-safe handle for running closures on the event loop of the current worker process.
+///
+/// The handle is created on the worker thread and can be sent to the background threads, e.g. the
+/// threads of an async runtime, to hand over the results without polling.
+///
+/// The event loop is woken up with a socket pair read by a connection of the worker process, which
+/// is created with the first handle and kept until the worker process exits. A dedicated
+/// connection is used instead of `ngx_notify`, as the latter keeps a single handler per process,
+/// already used by the NGINX thread pools.
+#[derive(Clone, Copy, Debug)]
+pub struct Notifier {
+    sender: &'static UnixStream,
+}
+
+impl Notifier {
+    /// Creates a handle for the current worker process.
+    ///
+    /// Returns `None` if the socket pair or the connection cannot be created, e.g. if
+    /// `worker_connections` are exhausted.
+    ///
+    /// Must be called on the worker thread.
+    pub fn new() -> Option<Self> {
+        if let Some((sender, _)) = CHANNEL.get() {
+            return Some(Self { sender });
+        }
+
+        let (sender, receiver) = UnixStream::pair().ok()?;
+        sender.set_nonblocking(true).ok()?;
+        receiver.set_nonblocking(true).ok()?;
+
+        unsafe { add_receiver(&receiver)? };
+
+        let (sender, _) = CHANNEL.get_or_init(|| (sender, receiver));
+        Some(Self { sender })
+    }
+
+    /// Schedules the closure to run on the worker thread.
+    ///
+    /// The closures run in the order of scheduling. The closures scheduled when the worker process
+    /// exits are never run.
+    ///
+    /// On error, the closure stays queued and runs after the next successful notification.
+    pub fn schedule<F>(&self, f: F) -> Result<(), NotifyError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let notify = {
+            let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+            queue.tasks.push(Box::new(f));
+            !core::mem::replace(&mut queue.notified, true)
+        };
+
+        if !notify {
+            return Ok(());
+        }
+
+        match (&*self.sender).write(&[0]) {
+            Ok(_) => Ok(()),
+            // the socket buffer is full of the notifications not yet read by the worker
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(_) => {
+                QUEUE.lock().unwrap_or_else(|e| e.into_inner()).notified = false;
+                Err(NotifyError)
+            }
+        }
+    }
+}
+
+/// Registers the connection reading the notifications.
+unsafe fn add_receiver(receiver: &UnixStream) -> Option<()> {
+    let log = (*ngx_cycle).log;
+
+    let c = ngx_get_connection(receiver.as_raw_fd(), log);
+    if c.is_null() {
+        return None;
+    }
+
+    let rev = (*c).read;
+    (*rev).handler = Some(notify_handler);
+    (*rev).log = log;
+    // the connection is kept open on exit, and is not reported as a leaked socket, as the channel
+    // of the master process
+    (*rev).set_channel(1);
+
+    if ngx_handle_read_event(rev, 0) != Status::NGX_OK.into() {
+        (*c).fd = -1;
+        ngx_free_connection(c);
+        return None;
+    }
+
+    Some(())
+}
+
+/// Queues the closure without notifying the event loop.
+///
+/// The closure runs with the next notification or [`run_queued`] call on the worker thread.
+pub(crate) fn enqueue<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    QUEUE.lock().unwrap_or_else(|e| e.into_inner()).tasks.push(Box::new(f));
+}
+
+/// Runs the queued closures. Must be called on the worker thread.
+pub(crate) fn run_queued() {
+    let tasks = {
+        let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        queue.notified = false;
+        core::mem::take(&mut queue.tasks)
+    };
+
+    for task in tasks {
+        task();
+    }
+}
+
+unsafe extern "C" fn notify_handler(ev: *mut ngx_event_t) {
+    if let Some((_, receiver)) = CHANNEL.get() {
+        let mut buf = [0u8; 64];
+        // the pending notifications are coalesced
+        loop {
+            match (&*receiver).read(&mut buf) {
+                Ok(1..) => continue,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                _ => break,
+            }
+        }
+    }
+    (*ev).set_ready(0);
+
+    run_queued();
+
+    if ngx_handle_read_event(ev, 0) != Status::NGX_OK.into() {
+        crate::ngx_log_error!(NGX_LOG_ALERT, (*ev).log, "notifier: failed to add event");
+    }
+}
@@ -0,0 +1,365 @@
+use core::ffi::{c_int, c_void};
+use core::future::{poll_fn, Future};
+use core::mem;
+use core::net::SocketAddr;
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use std::boxed::Box;
+use std::io;
+
+use crate::core::{socket_addr_to_sockaddr, Status};
+use crate::event::{add_timer, del_timer};
+use crate::ffi::*;
+
+/// Reads bytes from a source driven by the NGINX events.
+///
+/// Similar to the `AsyncRead` traits of the async runtimes, without depending on any of these.
+pub trait AsyncRead {
+    /// Attempts to read into `buf`, returning the number of bytes read, or `0` at the end of stream.
+    ///
+    /// Returns `Poll::Pending` and arranges for the task to be woken when the source is readable.
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+/// Writes bytes to a sink driven by the NGINX events.
+///
+/// Similar to the `AsyncWrite` traits of the async runtimes, without depending on any of these.
+pub trait AsyncWrite {
+    /// Attempts to write from `buf`, returning the number of bytes written.
+    ///
+    /// Returns `Poll::Pending` and arranges for the task to be woken when the sink is writable.
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
+
+    /// Attempts to shut down the write side of the sink.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+/// Size of the buffer for the text representation of the peer address, including the port.
+const PEER_NAME_LEN: usize = 64;
+
+/// Connection state with a stable address, referenced from the NGINX structures.
+struct PeerInner {
+    pc: ngx_peer_connection_t,
+    sockaddr: sockaddr_storage,
+    name: ngx_str_t,
+    name_buf: [u8; PEER_NAME_LEN],
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+/// Outgoing TCP connection established with `ngx_event_connect_peer` and driven by the events of
+/// the current worker process.
+///
+/// The connection is used from the futures running on the worker thread, e.g. spawned with
+/// [`spawn`](crate::event::spawn). The optional read and write timeouts are applied to each
+/// operation that cannot complete immediately.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#connection>
+pub struct PeerConnection {
+    inner: Box<PeerInner>,
+    read_timeout: Option<ngx_msec_t>,
+    write_timeout: Option<ngx_msec_t>,
+}
+
+impl PeerConnection {
+    /// Connects to the address, failing with [`io::ErrorKind::TimedOut`] if the connection is not
+    /// established within `timeout`.
+    ///
+    /// Errors are logged to the cycle log.
+    ///
+    /// Must be awaited on the worker thread.
+    pub async fn connect(addr: SocketAddr, timeout: Duration) -> io::Result<Self> {
+        let mut inner = Box::new(PeerInner {
+            pc: unsafe { mem::zeroed() },
+            sockaddr: unsafe { mem::zeroed() },
+            name: ngx_str_t::empty(),
+            name_buf: [0; PEER_NAME_LEN],
+            read_waker: None,
+            write_waker: None,
+        });
+
+        let this = &mut *inner;
+        let socklen = socket_addr_to_sockaddr(&addr, &mut this.sockaddr);
+
+        unsafe {
+            let len = ngx_sock_ntop(
+                ptr::addr_of_mut!(this.sockaddr).cast(),
+                socklen,
+                this.name_buf.as_mut_ptr(),
+                this.name_buf.len(),
+                1,
+            );
+            this.name = ngx_str_t {
+                len,
+                data: this.name_buf.as_mut_ptr(),
+            };
+
+            this.pc.sockaddr = ptr::addr_of_mut!(this.sockaddr).cast();
+            this.pc.socklen = socklen;
+            this.pc.name = &mut this.name;
+            this.pc.get = Some(ngx_event_get_peer);
+            this.pc.log = (*ngx_cycle).log;
+            this.pc.set_log_error(NGX_ERROR_ERR as _);
+        }
+
+        let mut peer = PeerConnection {
+            inner,
+            read_timeout: None,
+            write_timeout: None,
+        };
+
+        let rc = unsafe { ngx_event_connect_peer(&mut peer.inner.pc) };
+
+        match Status(rc) {
+            Status::NGX_OK | Status::NGX_AGAIN => {}
+            Status::NGX_DECLINED => return Err(io::ErrorKind::ConnectionRefused.into()),
+            _ => return Err(io::Error::other("ngx_event_connect_peer() failed")),
+        }
+
+        let c = peer.as_ngx_connection();
+        unsafe {
+            (*c).data = ptr::addr_of_mut!(*peer.inner).cast();
+            (*(*c).read).handler = Some(peer_read_handler);
+            (*(*c).write).handler = Some(peer_write_handler);
+        }
+
+        if rc == Status::NGX_AGAIN.into() {
+            let wev = unsafe { (*c).write };
+            unsafe { add_timer(wev, duration_to_msec(timeout)) };
+
+            poll_fn(|cx| unsafe {
+                if (*wev).timedout() != 0 || (*wev).ready() != 0 {
+                    return Poll::Ready(());
+                }
+                peer.inner.write_waker = Some(cx.waker().clone());
+                Poll::Pending
+            })
+            .await;
+
+            unsafe {
+                if (*wev).timedout() != 0 {
+                    (*wev).set_timedout(0);
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                if (*wev).timer_set() != 0 {
+                    del_timer(wev);
+                }
+            }
+
+            peer.test_connect()?;
+        }
+
+        Ok(peer)
+    }
+
+    /// Sets the timeout for the read operations.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout.map(duration_to_msec);
+    }
+
+    /// Sets the timeout for the write operations.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout.map(duration_to_msec);
+    }
+
+    /// Reads into `buf`, returning the number of bytes read, or `0` at the end of stream.
+    pub fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> impl Future<Output = io::Result<usize>> + 'a {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_read(cx, buf))
+    }
+
+    /// Writes the entire `buf`.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = poll_fn(|cx| Pin::new(&mut *self).poll_write(cx, buf)).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    /// Shuts down the write side of the connection.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_shutdown(cx)).await
+    }
+
+    /// Returns the peer address.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        unsafe { crate::core::sockaddr_to_socket_addr(self.inner.pc.sockaddr, self.inner.pc.socklen) }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_connection_t`.
+    pub fn as_ngx_connection(&self) -> *mut ngx_connection_t {
+        self.inner.pc.connection
+    }
+
+    /// Checks the result of a non-blocking connect.
+    fn test_connect(&self) -> io::Result<()> {
+        let c = self.as_ngx_connection();
+        let mut err: c_int = 0;
+        let mut len = mem::size_of::<c_int>() as socklen_t;
+
+        let rc = unsafe {
+            getsockopt(
+                (*c).fd,
+                SOL_SOCKET as _,
+                SO_ERROR as _,
+                ptr::addr_of_mut!(err).cast::<c_void>(),
+                &mut len,
+            )
+        };
+
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        Ok(())
+    }
+
+    /// Waits for the event, scheduling the timeout and the waker.
+    fn poll_event(&mut self, cx: &mut Context<'_>, write: bool) -> Poll<io::Result<()>> {
+        let c = self.as_ngx_connection();
+        let (ev, timeout) = unsafe {
+            if write {
+                ((*c).write, self.write_timeout)
+            } else {
+                ((*c).read, self.read_timeout)
+            }
+        };
+
+        unsafe {
+            if (*ev).timedout() != 0 {
+                (*ev).set_timedout(0);
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+
+            let rc = if write {
+                ngx_handle_write_event(ev, 0)
+            } else {
+                ngx_handle_read_event(ev, 0)
+            };
+            if rc != Status::NGX_OK.into() {
+                return Poll::Ready(Err(io::Error::other("failed to add event")));
+            }
+
+            if let Some(timeout) = timeout {
+                add_timer(ev, timeout);
+            }
+        }
+
+        let waker = Some(cx.waker().clone());
+        if write {
+            self.inner.write_waker = waker;
+        } else {
+            self.inner.read_waker = waker;
+        }
+
+        Poll::Pending
+    }
+}
+
+impl AsyncRead for PeerConnection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let c = this.as_ngx_connection();
+
+        let n = unsafe {
+            let rev = (*c).read;
+            if (*rev).timer_set() != 0 && (*rev).timedout() == 0 {
+                del_timer(rev);
+            }
+            if (*rev).timedout() != 0 {
+                (*rev).set_timedout(0);
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+
+            let recv = (*c).recv.expect("recv handler");
+            recv(c, buf.as_mut_ptr(), buf.len())
+        };
+
+        match n {
+            n if n >= 0 => Poll::Ready(Ok(n as usize)),
+            n if n == NGX_AGAIN as isize => match this.poll_event(cx, false) {
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                _ => Poll::Pending,
+            },
+            _ => Poll::Ready(Err(io::Error::other("recv() failed"))),
+        }
+    }
+}
+
+impl AsyncWrite for PeerConnection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let c = this.as_ngx_connection();
+
+        let n = unsafe {
+            let wev = (*c).write;
+            if (*wev).timer_set() != 0 && (*wev).timedout() == 0 {
+                del_timer(wev);
+            }
+            if (*wev).timedout() != 0 {
+                (*wev).set_timedout(0);
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+
+            let send = (*c).send.expect("send handler");
+            send(c, buf.as_ptr().cast_mut(), buf.len())
+        };
+
+        match n {
+            n if n >= 0 => Poll::Ready(Ok(n as usize)),
+            n if n == NGX_AGAIN as isize => match this.poll_event(cx, true) {
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                _ => Poll::Pending,
+            },
+            _ => Poll::Ready(Err(io::Error::other("send() failed"))),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let c = self.as_ngx_connection();
+        // SHUT_WR
+        if unsafe { shutdown((*c).fd, 1) } == -1 {
+            return Poll::Ready(Err(io::Error::last_os_error()));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        let c = self.as_ngx_connection();
+        if !c.is_null() {
+            // deletes the pending timers and events
+            unsafe { ngx_close_connection(c) };
+            self.inner.pc.connection = ptr::null_mut();
+        }
+    }
+}
+
+unsafe extern "C" fn peer_read_handler(ev: *mut ngx_event_t) {
+    let c = (*ev).data.cast::<ngx_connection_t>();
+    let inner = (*c).data.cast::<PeerInner>();
+    if let Some(waker) = (*inner).read_waker.take() {
+        waker.wake();
+    }
+}
+
+unsafe extern "C" fn peer_write_handler(ev: *mut ngx_event_t) {
+    let c = (*ev).data.cast::<ngx_connection_t>();
+    let inner = (*c).data.cast::<PeerInner>();
+    if let Some(waker) = (*inner).write_waker.take() {
+        waker.wake();
+    }
+}
+
+fn duration_to_msec(d: Duration) -> ngx_msec_t {
+    d.as_millis().min(ngx_msec_t::MAX as u128 / 2) as ngx_msec_t
+}
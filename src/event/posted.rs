@@ -0,0 +1,166 @@
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+
+#[cfg(feature = "alloc")]
+use crate::allocator::AllocError;
+use crate::core::{Pool, Queue};
+use crate::event::closure::ClosureEvent;
+use crate::ffi::*;
+
+/// Queue of the posted events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostedQueue {
+    /// `ngx_posted_events`, processed in the current iteration of the event loop.
+    Current,
+    /// `ngx_posted_next_events`, processed in the next iteration of the event loop, after polling
+    /// for the I/O events.
+    Next,
+}
+
+impl PostedQueue {
+    fn queue(self) -> &'static Queue<ngx_event_t> {
+        let q = match self {
+            PostedQueue::Current => ptr::addr_of_mut!(ngx_posted_events),
+            PostedQueue::Next => ptr::addr_of_mut!(ngx_posted_next_events),
+        };
+        unsafe { Queue::from_ngx_queue(q) }
+    }
+}
+
+/// Adds the event to the queue of the posted events, as `ngx_post_event` does.
+///
+/// # Safety
+/// The event must be valid and initialized, and must stay in place until processed or deleted.
+/// Must be called on the worker thread.
+pub unsafe fn post_event(ev: *mut ngx_event_t, queue: PostedQueue) {
+    if (*ev).posted() == 0 {
+        (*ev).set_posted(1);
+        queue.queue().insert_tail(NonNull::new_unchecked(ev));
+    }
+}
+
+/// Removes the event from the queue of the posted events, as `ngx_delete_posted_event` does.
+///
+/// # Safety
+/// The event must be valid and posted. Must be called on the worker thread.
+pub unsafe fn delete_posted_event(ev: *mut ngx_event_t) {
+    (*ev).set_posted(0);
+    Queue::remove(NonNull::new_unchecked(ev));
+}
+
+/// Reusable event running a closure on the event loop of the current worker process.
+///
+/// The event and the closure are allocated from the pool, and the event is removed from the queue
+/// when the pool is destroyed, thus it cannot outlive the request, connection or cycle owning the
+/// pool.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#posted_events>
+#[derive(Clone, Copy)]
+pub struct PostedEvent<'a> {
+    event: NonNull<ngx_event_t>,
+    _p: PhantomData<&'a Pool>,
+}
+
+impl<'a> PostedEvent<'a> {
+    /// Creates an event calling `handler` each time it is processed.
+    ///
+    /// The event uses the log of the pool.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn new<F>(pool: &'a Pool, handler: F) -> Option<Self>
+    where
+        F: FnMut() + 'static,
+    {
+        Some(Self {
+            event: ClosureEvent::create(pool, handler)?,
+            _p: PhantomData,
+        })
+    }
+
+    /// Posts the event to the queue, unless already posted.
+    pub fn post(&self, queue: PostedQueue) {
+        unsafe { post_event(self.event.as_ptr(), queue) };
+    }
+
+    /// Removes the event from the queue if posted.
+    pub fn cancel(&self) {
+        let ev = self.event.as_ptr();
+        unsafe {
+            if (*ev).posted() != 0 {
+                delete_posted_event(ev);
+            }
+        }
+    }
+
+    /// Returns `true` if the event is posted.
+    pub fn is_posted(&self) -> bool {
+        unsafe { self.event.as_ref().posted() != 0 }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_event_t`.
+    pub fn as_ngx_event(&self) -> *mut ngx_event_t {
+        self.event.as_ptr()
+    }
+}
+
+/// Runs the closure once on the event loop of the current worker process, after the currently
+/// processed event.
+///
+/// Useful for yielding to the other events without waiting for I/O. The closure is leaked without
+/// running if the worker process exits before the event is processed.
+///
+/// Must be called on the worker thread.
+#[cfg(feature = "alloc")]
+pub fn post<F>(handler: F) -> Result<(), AllocError>
+where
+    F: FnOnce() + 'static,
+{
+    post_once(PostedQueue::Current, handler)
+}
+
+/// Runs the closure once on the next iteration of the event loop of the current worker process,
+/// after polling for the I/O events.
+///
+/// Must be called on the worker thread.
+#[cfg(feature = "alloc")]
+pub fn post_next<F>(handler: F) -> Result<(), AllocError>
+where
+    F: FnOnce() + 'static,
+{
+    post_once(PostedQueue::Next, handler)
+}
+
+#[cfg(feature = "alloc")]
+fn post_once<F>(queue: PostedQueue, handler: F) -> Result<(), AllocError>
+where
+    F: FnOnce() + 'static,
+{
+    use crate::allocator::Box;
+
+    struct OneShot<F> {
+        event: ngx_event_t,
+        handler: F,
+    }
+
+    unsafe extern "C" fn one_shot_handler<F: FnOnce()>(ev: *mut ngx_event_t) {
+        let data = Box::into_inner(Box::from_raw((*ev).data.cast::<OneShot<F>>()));
+        (data.handler)();
+    }
+
+    let data = Box::try_new(OneShot {
+        event: unsafe { core::mem::zeroed() },
+        handler,
+    })?;
+    let data = Box::into_raw(data);
+
+    unsafe {
+        let ev = &mut (*data).event;
+        ev.data = data.cast();
+        ev.handler = Some(one_shot_handler::<F>);
+        ev.log = (*ngx_cycle).log;
+
+        post_event(ev, queue);
+    }
+
+    Ok(())
+}
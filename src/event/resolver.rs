@@ -0,0 +1,256 @@
+use core::cell::RefCell;
+use core::ffi::CStr;
+use core::fmt;
+use core::future::Future;
+use core::net::{IpAddr, SocketAddr};
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+use core::task::{Context, Poll, Waker};
+
+use std::boxed::Box;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use crate::core::{sockaddr_to_socket_addr, Status};
+use crate::ffi::*;
+
+/// ResolveError - the name cannot be resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// No `resolver` is configured.
+    NoResolver,
+    /// Memory allocation or sending of the DNS query failed.
+    Failed,
+    /// The DNS query failed or timed out, with the resolver error code, e.g. `NGX_RESOLVE_NXDOMAIN`
+    /// or `NGX_RESOLVE_TIMEDOUT`.
+    Resolve(ngx_int_t),
+}
+
+impl ResolveError {
+    /// Returns `true` if the resolver did not respond within `resolver_timeout`.
+    pub fn is_timeout(&self) -> bool {
+        *self == ResolveError::Resolve(NGX_RESOLVE_TIMEDOUT as _)
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::NoResolver => "no resolver defined".fmt(fmt),
+            ResolveError::Failed => "ngx_resolve_name() failed".fmt(fmt),
+            ResolveError::Resolve(code) => {
+                let err = unsafe { CStr::from_ptr(ngx_resolver_strerror(*code).cast()) };
+                write!(fmt, "could not be resolved ({}: {})", code, err.to_string_lossy())
+            }
+        }
+    }
+}
+
+type ResolveHandler = Box<dyn FnOnce(Result<Vec<SocketAddr>, ResolveError>)>;
+
+/// Pending name resolution, referenced from the resolver context.
+struct ResolveTask {
+    name: Vec<u8>,
+    handler: ResolveHandler,
+}
+
+/// Handle for resolving the names with an [`ngx_resolver_t`] configured with the `resolver`
+/// directive, such as the one of a `location`.
+///
+/// The addresses are returned with the port set to `0`.
+///
+/// See <https://nginx.org/en/docs/http/ngx_http_core_module.html#resolver>
+#[derive(Clone, Copy, Debug)]
+pub struct Resolver {
+    resolver: NonNull<ngx_resolver_t>,
+    timeout: ngx_msec_t,
+}
+
+impl Resolver {
+    /// Creates a new `Resolver` from an [`ngx_resolver_t`] pointer and the timeout of the queries,
+    /// in milliseconds.
+    ///
+    /// # Safety
+    /// The caller has provided a valid non-null pointer to an `ngx_resolver_t`, which outlives the
+    /// returned object.
+    pub unsafe fn from_ngx_resolver(resolver: *mut ngx_resolver_t, timeout: ngx_msec_t) -> Self {
+        Self {
+            resolver: NonNull::new(resolver).expect("non-null resolver"),
+            timeout,
+        }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_resolver_t`.
+    pub fn as_ngx_resolver(&self) -> *mut ngx_resolver_t {
+        self.resolver.as_ptr()
+    }
+
+    /// Resolves the name and calls `handler` with the addresses on the worker thread.
+    ///
+    /// The names in the form of an IP address are returned without querying the resolver. The
+    /// handler can be called before this method returns, e.g. for the names in the resolver cache.
+    /// The objects referenced from the handler, such as a request, must outlive the query.
+    ///
+    /// Returns an error without calling `handler` if the query cannot be started.
+    ///
+    /// Must be called on the worker thread.
+    pub fn resolve_with<F>(&self, name: &[u8], handler: F) -> Result<(), ResolveError>
+    where
+        F: FnOnce(Result<Vec<SocketAddr>, ResolveError>) + 'static,
+    {
+        self.start(name, Box::new(handler)).map(|_| ())
+    }
+
+    /// Returns a future resolving the name to the addresses.
+    ///
+    /// The query is canceled if the future is dropped before completion.
+    ///
+    /// Must be awaited on the worker thread, e.g. from a future passed to
+    /// [`spawn`](crate::event::spawn).
+    pub fn resolve(&self, name: &[u8]) -> Resolve {
+        Resolve {
+            resolver: *self,
+            name: Some(name.to_vec()),
+            state: Rc::new(RefCell::new(ResolveState {
+                result: None,
+                waker: None,
+            })),
+            ctx: None,
+        }
+    }
+
+    /// Starts the query, returning the resolver context.
+    ///
+    /// The context is only valid until the handler is called.
+    fn start(&self, name: &[u8], handler: ResolveHandler) -> Result<Option<NonNull<ngx_resolver_ctx_t>>, ResolveError> {
+        if let Some(addr) = core::str::from_utf8(name).ok().and_then(|x| x.parse::<IpAddr>().ok()) {
+            handler(Ok(std::vec![SocketAddr::new(addr, 0)]));
+            return Ok(None);
+        }
+
+        let ctx = unsafe { ngx_resolve_start(self.resolver.as_ptr(), ptr::null_mut()) };
+        // NGX_NO_RESOLVER
+        if ctx as isize == -1 {
+            return Err(ResolveError::NoResolver);
+        }
+        let ctx = NonNull::new(ctx).ok_or(ResolveError::Failed)?;
+
+        let task = Box::into_raw(Box::new(ResolveTask {
+            name: name.to_vec(),
+            handler,
+        }));
+
+        unsafe {
+            let c = ctx.as_ptr();
+            (*c).name = ngx_str_t {
+                len: (*task).name.len(),
+                data: (*task).name.as_mut_ptr(),
+            };
+            (*c).handler = Some(resolve_handler);
+            (*c).data = task.cast();
+            (*c).timeout = self.timeout;
+            // the pending queries do not delay the graceful shutdown
+            (*c).set_cancelable(1);
+
+            // the context is freed on failure
+            if ngx_resolve_name(c) != Status::NGX_OK.into() {
+                drop(Box::from_raw(task));
+                return Err(ResolveError::Failed);
+            }
+        }
+
+        Ok(Some(ctx))
+    }
+}
+
+unsafe extern "C" fn resolve_handler(ctx: *mut ngx_resolver_ctx_t) {
+    let task = Box::from_raw((*ctx).data.cast::<ResolveTask>());
+
+    let result = if (*ctx).state != Status::NGX_OK.into() {
+        Err(ResolveError::Resolve((*ctx).state))
+    } else {
+        let addrs = if (*ctx).naddrs > 0 {
+            core::slice::from_raw_parts((*ctx).addrs, (*ctx).naddrs)
+        } else {
+            &[]
+        };
+        Ok(addrs
+            .iter()
+            .filter_map(|x| sockaddr_to_socket_addr(x.sockaddr, x.socklen))
+            .collect())
+    };
+
+    // the name is used to find the node of the context
+    ngx_resolve_name_done(ctx);
+
+    let ResolveTask { handler, .. } = *task;
+    handler(result);
+}
+
+/// Result of a [`Resolve`] future, shared with the resolver handler.
+struct ResolveState {
+    result: Option<Result<Vec<SocketAddr>, ResolveError>>,
+    waker: Option<Waker>,
+}
+
+/// Future resolving a name to the addresses, created with [`Resolver::resolve`].
+pub struct Resolve {
+    resolver: Resolver,
+    name: Option<Vec<u8>>,
+    state: Rc<RefCell<ResolveState>>,
+    ctx: Option<NonNull<ngx_resolver_ctx_t>>,
+}
+
+impl Future for Resolve {
+    type Output = Result<Vec<SocketAddr>, ResolveError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        if let Some(name) = this.name.take() {
+            let state = this.state.clone();
+            let handler = Box::new(move |result: Result<Vec<SocketAddr>, ResolveError>| {
+                let mut state = state.borrow_mut();
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+
+            match this.resolver.start(&name, handler) {
+                Ok(ctx) => this.ctx = ctx,
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+
+        let mut state = this.state.borrow_mut();
+        if let Some(result) = state.result.take() {
+            // the context is freed by the handler
+            this.ctx = None;
+            return Poll::Ready(result);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Resolve {
+    fn drop(&mut self) {
+        let Some(ctx) = self.ctx.take() else {
+            return;
+        };
+
+        if self.state.borrow().result.is_some() {
+            return;
+        }
+
+        unsafe {
+            let task = Box::from_raw((*ctx.as_ptr()).data.cast::<ResolveTask>());
+            ngx_resolve_name_done(ctx.as_ptr());
+            drop(task);
+        }
+    }
+}
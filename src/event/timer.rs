@@ -0,0 +1,104 @@
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::time::Duration;
+
+use crate::core::Pool;
+use crate::event::closure::ClosureEvent;
+use crate::ffi::*;
+
+/// Adds the event to the timer tree, as `ngx_add_timer` does.
+///
+/// If the event timer is already set and the new expiration time differs by less than
+/// `NGX_TIMER_LAZY_DELAY` milliseconds, the timer is left as is.
+///
+/// # Safety
+/// The event must be valid and initialized, and must stay in place until the timer expires or is
+/// deleted. Must be called on the worker thread.
+pub unsafe fn add_timer(ev: *mut ngx_event_t, timer: ngx_msec_t) {
+    let key = ngx_current_msec.wrapping_add(timer);
+
+    if (*ev).timer_set() != 0 {
+        // the timer keys are compared as signed values to handle the overflow
+        let diff = key.wrapping_sub((*ev).timer.key) as ngx_msec_int_t;
+        if diff.unsigned_abs() < NGX_TIMER_LAZY_DELAY as _ {
+            return;
+        }
+
+        del_timer(ev);
+    }
+
+    (*ev).timer.key = key;
+    ngx_rbtree_insert(ptr::addr_of_mut!(ngx_event_timer_rbtree), &mut (*ev).timer);
+    (*ev).set_timer_set(1);
+}
+
+/// Removes the event from the timer tree, as `ngx_del_timer` does.
+///
+/// # Safety
+/// The event must be valid and have the timer set. Must be called on the worker thread.
+pub unsafe fn del_timer(ev: *mut ngx_event_t) {
+    ngx_rbtree_delete(ptr::addr_of_mut!(ngx_event_timer_rbtree), &mut (*ev).timer);
+    (*ev).timer.left = ptr::null_mut();
+    (*ev).timer.right = ptr::null_mut();
+    (*ev).timer.parent = ptr::null_mut();
+    (*ev).set_timer_set(0);
+}
+
+/// Timer running a closure on the event loop of the current worker process.
+///
+/// The timer and the closure are allocated from the pool, and the timer is deleted when the pool is
+/// destroyed, thus it cannot outlive the request, connection or cycle owning the pool.
+///
+/// The timers are `cancelable`: pending timers do not delay the graceful shutdown of the worker
+/// process and are silently discarded.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#timer_event>
+#[derive(Clone, Copy)]
+pub struct Timer<'a> {
+    event: NonNull<ngx_event_t>,
+    _p: PhantomData<&'a Pool>,
+}
+
+impl<'a> Timer<'a> {
+    /// Creates an inactive timer calling `handler` on expiration.
+    ///
+    /// The timer event uses the log of the pool.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn new<F>(pool: &'a Pool, handler: F) -> Option<Self>
+    where
+        F: FnMut() + 'static,
+    {
+        let event = ClosureEvent::create(pool, handler)?;
+        // pending timers do not delay the graceful shutdown
+        unsafe { (*event.as_ptr()).set_cancelable(1) };
+
+        Some(Self { event, _p: PhantomData })
+    }
+
+    /// Schedules the timer to expire after `delay`, rescheduling it if already active.
+    pub fn add(&self, delay: Duration) {
+        let msec = delay.as_millis().min(ngx_msec_t::MAX as u128 / 2) as ngx_msec_t;
+        unsafe { add_timer(self.event.as_ptr(), msec) };
+    }
+
+    /// Cancels the timer if active.
+    pub fn cancel(&self) {
+        let ev = self.event.as_ptr();
+        unsafe {
+            if (*ev).timer_set() != 0 {
+                del_timer(ev);
+            }
+        }
+    }
+
+    /// Returns `true` if the timer is scheduled.
+    pub fn is_active(&self) -> bool {
+        unsafe { self.event.as_ref().timer_set() != 0 }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_event_t`.
+    pub fn as_ngx_event(&self) -> *mut ngx_event_t {
+        self.event.as_ptr()
+    }
+}
@@ -1,5 +1,7 @@
 use core::ffi::c_void;
+use core::slice;
 
+use crate::core::NgxStr;
 use crate::ffi::*;
 
 /// # Safety
@@ -61,3 +63,190 @@ pub unsafe fn ngx_http_conf_upstream_srv_conf_mutable<T>(
     }
     Some(*(*us).srv_conf.add(module.ctx_index) as *mut T)
 }
+
+/// Read-only view of the [`ngx_http_core_srv_conf_t`] structure.
+///
+/// Contains the core module configuration of a `server` block.
+#[repr(transparent)]
+pub struct CoreSrvConf(ngx_http_core_srv_conf_t);
+
+impl CoreSrvConf {
+    /// The first name specified in the `server_name` directive.
+    ///
+    /// This is the name used in redirects and in the `$server_name` variable.
+    pub fn server_name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.server_name) }
+    }
+
+    /// All the names specified in the `server_name` directive, including wildcard and regex names.
+    pub fn server_names(&self) -> impl Iterator<Item = &NgxStr> {
+        let names: &[ngx_http_server_name_t] = if self.0.server_names.nelts == 0 {
+            &[]
+        } else {
+            // SAFETY: `server_names` is an array of `ngx_http_server_name_t` initialized by the core module
+            unsafe { slice::from_raw_parts(self.0.server_names.elts.cast(), self.0.server_names.nelts) }
+        };
+        names.iter().map(|sn| unsafe { NgxStr::from_ngx_str(sn.name) })
+    }
+
+    /// Size of the buffer for reading client request header (`client_header_buffer_size`).
+    pub fn client_header_buffer_size(&self) -> usize {
+        self.0.client_header_buffer_size
+    }
+
+    /// Timeout for reading client request header (`client_header_timeout`), in milliseconds.
+    pub fn client_header_timeout(&self) -> ngx_msec_t {
+        self.0.client_header_timeout
+    }
+
+    /// Returns the inner data structure that the CoreSrvConf object is wrapping.
+    pub fn get_inner(&self) -> &ngx_http_core_srv_conf_t {
+        &self.0
+    }
+}
+
+/// The kind of a `location` match.
+///
+/// See <https://nginx.org/en/docs/http/ngx_http_core_module.html#location>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocationMatch {
+    /// Exact match location (`location = /uri`).
+    Exact,
+    /// Prefix location (`location /uri`).
+    Prefix,
+    /// Prefix location that disables regular expression checks when matched (`location ^~ /uri`).
+    PrefixNoRegex,
+    /// Regular expression location (`location ~ regex` or `location ~* regex`).
+    Regex,
+    /// Named location (`location @name`).
+    Named,
+    /// Implicit location created by an `if` or `limit_except` block.
+    Unnamed,
+}
+
+/// Read-only view of the [`ngx_http_core_loc_conf_t`] structure.
+///
+/// Contains the core module configuration of a `location`, `if` or `limit_except` block.
+#[repr(transparent)]
+pub struct CoreLocConf(ngx_http_core_loc_conf_t);
+
+impl CoreLocConf {
+    /// Location name, i.e. the URI prefix, exact URI or regular expression source.
+    ///
+    /// For `if` and `limit_except` blocks this is the name of the enclosing location.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.name) }
+    }
+
+    /// The kind of the location match.
+    pub fn match_kind(&self) -> LocationMatch {
+        if self.0.noname() != 0 {
+            return LocationMatch::Unnamed;
+        }
+        if self.0.named() != 0 {
+            return LocationMatch::Named;
+        }
+        if self.0.exact_match() != 0 {
+            return LocationMatch::Exact;
+        }
+        #[cfg(ngx_feature = "pcre")]
+        if !self.0.regex.is_null() {
+            return LocationMatch::Regex;
+        }
+        if self.0.noregex() != 0 {
+            return LocationMatch::PrefixNoRegex;
+        }
+        LocationMatch::Prefix
+    }
+
+    /// Returns `true` if the location is marked with the `internal` directive.
+    pub fn is_internal(&self) -> bool {
+        self.0.internal != 0
+    }
+
+    /// Document root specified with the `root` directive.
+    ///
+    /// Returns `None` if the location uses `alias` instead.
+    /// The value may contain variables, see [`CoreLocConf::root_has_variables`].
+    pub fn root(&self) -> Option<&NgxStr> {
+        if self.0.alias != 0 {
+            return None;
+        }
+        Some(unsafe { NgxStr::from_ngx_str(self.0.root) })
+    }
+
+    /// Path specified with the `alias` directive.
+    ///
+    /// Returns `None` if the location uses `root` instead.
+    /// The value may contain variables, see [`CoreLocConf::root_has_variables`].
+    pub fn alias(&self) -> Option<&NgxStr> {
+        if self.0.alias == 0 {
+            return None;
+        }
+        Some(unsafe { NgxStr::from_ngx_str(self.0.root) })
+    }
+
+    /// Returns `true` if the `root` or `alias` value contains variables and has to be evaluated for
+    /// each request.
+    pub fn root_has_variables(&self) -> bool {
+        !self.0.root_lengths.is_null()
+    }
+
+    /// Maximum allowed size of the client request body (`client_max_body_size`).
+    ///
+    /// Zero disables the check.
+    pub fn client_max_body_size(&self) -> off_t {
+        self.0.client_max_body_size
+    }
+
+    /// Size of the buffer for reading client request body (`client_body_buffer_size`).
+    pub fn client_body_buffer_size(&self) -> usize {
+        self.0.client_body_buffer_size
+    }
+
+    /// Timeout for reading client request body (`client_body_timeout`), in milliseconds.
+    pub fn client_body_timeout(&self) -> ngx_msec_t {
+        self.0.client_body_timeout
+    }
+
+    /// Timeout for transmitting a response to the client (`send_timeout`), in milliseconds.
+    pub fn send_timeout(&self) -> ngx_msec_t {
+        self.0.send_timeout
+    }
+
+    /// Rate limit of the response transmission to a client (`limit_rate`).
+    ///
+    /// The value can be evaluated with [`Request::get_complex_value`](crate::http::Request::get_complex_value).
+    pub fn limit_rate(&self) -> Option<&ngx_http_complex_value_t> {
+        unsafe { self.0.limit_rate.as_ref() }
+    }
+
+    /// Initial amount after which the response transmission is rate limited (`limit_rate_after`).
+    ///
+    /// The value can be evaluated with [`Request::get_complex_value`](crate::http::Request::get_complex_value).
+    pub fn limit_rate_after(&self) -> Option<&ngx_http_complex_value_t> {
+        unsafe { self.0.limit_rate_after.as_ref() }
+    }
+
+    /// Returns `true` if `sendfile` is enabled.
+    pub fn sendfile(&self) -> bool {
+        self.0.sendfile != 0
+    }
+
+    /// Resolver specified with the `resolver` directive, using the `resolver_timeout`.
+    ///
+    /// If the directive is not specified, NGINX merges a resolver without name servers, and the
+    /// queries fail with [`ResolveError::NoResolver`](crate::event::ResolveError).
+    ///
+    /// # Panics
+    /// Panics if called before the configuration is merged.
+    #[cfg(feature = "std")]
+    pub fn resolver(&self) -> crate::event::Resolver {
+        unsafe { crate::event::Resolver::from_ngx_resolver(self.0.resolver, self.0.resolver_timeout) }
+    }
+
+    /// Returns the inner data structure that the CoreLocConf object is wrapping.
+    pub fn get_inner(&self) -> &ngx_http_core_loc_conf_t {
+        &self.0
+    }
+}
@@ -0,0 +1,126 @@
+use core::ptr;
+
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Request};
+
+/// A static file opened for sending in a response.
+///
+/// The file is opened through the open file cache configured for the current location, with the
+/// same `disable_symlinks`, `directio` and `read_ahead` settings as used by the `ngx_http_static_module`.
+///
+/// See <https://nginx.org/en/docs/http/ngx_http_core_module.html#open_file_cache>
+pub struct FileResponse {
+    name: ngx_str_t,
+    info: OpenFileInfo,
+}
+
+impl FileResponse {
+    /// Opens a file at `path` for the request.
+    ///
+    /// Returns `Err` with the status code appropriate for the response, e.g.
+    /// [`HTTPStatus::NOT_FOUND`] for missing files and [`HTTPStatus::FORBIDDEN`] for files
+    /// that cannot be accessed. The failures are logged to the request log.
+    pub fn open(request: &mut Request, path: &[u8]) -> Result<Self, HTTPStatus> {
+        let r: *mut ngx_http_request_t = request.into();
+        let clcf = request.core_loc_conf().get_inner();
+        let pool = request.pool();
+
+        // nginx expects a null-terminated file name allocated for the lifetime of the request
+        let data = pool.alloc_unaligned(path.len() + 1) as *mut u8;
+        if data.is_null() {
+            return Err(HTTPStatus::INTERNAL_SERVER_ERROR);
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(path.as_ptr(), data, path.len());
+            *data.add(path.len()) = 0;
+        }
+        let mut name = ngx_str_t { len: path.len(), data };
+
+        let mut info = OpenFileInfo::default();
+        info.set_read_ahead(clcf.read_ahead)
+            .set_directio(clcf.directio)
+            .set_valid(clcf.open_file_cache_valid)
+            .set_min_uses(clcf.open_file_cache_min_uses)
+            .set_errors(clcf.open_file_cache_errors != 0)
+            .set_events(clcf.open_file_cache_events != 0);
+
+        if unsafe { ngx_http_set_disable_symlinks(r, clcf as *const _ as *mut _, &mut name, info.get_inner_mut()) }
+            != Status::NGX_OK.into()
+        {
+            return Err(HTTPStatus::INTERNAL_SERVER_ERROR);
+        }
+
+        if let Err(err) = unsafe { open_cached_file(clcf.open_file_cache, &mut name, &mut info, pool) } {
+            let (status, level) = match err as u32 {
+                NGX_ENOENT | NGX_ENOTDIR | NGX_ENAMETOOLONG => (HTTPStatus::NOT_FOUND, NGX_LOG_ERR),
+                NGX_EACCES | NGX_EMLINK | NGX_ELOOP => (HTTPStatus::FORBIDDEN, NGX_LOG_ERR),
+                _ => (HTTPStatus::INTERNAL_SERVER_ERROR, NGX_LOG_CRIT),
+            };
+
+            let log = request.log();
+            if (status != HTTPStatus::NOT_FOUND || clcf.log_not_found != 0)
+                && unsafe { (*log).log_level } >= level as ngx_uint_t
+            {
+                let failed = info.failed().and_then(|x| x.to_str().ok()).unwrap_or("open()");
+                let mut buf = [const { core::mem::MaybeUninit::<u8>::uninit() }; crate::log::LOG_BUFFER_SIZE];
+                let message =
+                    crate::log::write_fmt(&mut buf, format_args!("{} \"{}\" failed", failed, path.escape_ascii()));
+                unsafe { crate::log::log_error(level as _, log, err, message) };
+            }
+
+            return Err(status);
+        }
+
+        if !info.is_file() {
+            return Err(HTTPStatus::NOT_FOUND);
+        }
+
+        Ok(Self { name, info })
+    }
+
+    /// Information about the opened file.
+    pub fn info(&self) -> &OpenFileInfo {
+        &self.info
+    }
+
+    /// Sends the response headers and the file contents.
+    ///
+    /// The response is sent with the `200 OK` status, `Content-Length`, `Last-Modified` and `ETag`
+    /// headers and the content type derived from the request URI. Range requests are allowed.
+    pub fn send(&self, request: &mut Request) -> Status {
+        let rc = request.discard_request_body();
+        if rc != Status::NGX_OK {
+            return rc;
+        }
+
+        request.set_status(HTTPStatus::OK);
+        request.set_content_length_n(self.info.size() as usize);
+        request.set_last_modified_time(self.info.mtime());
+
+        if request.set_etag() != Status::NGX_OK || request.set_content_type() != Status::NGX_OK {
+            return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+        }
+
+        request.set_allow_ranges(true);
+
+        let mut buf = match request.pool().create_file_buffer(&self.info, self.name, request.log()) {
+            Some(buf) => buf,
+            None => return HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+        };
+
+        buf.set_last_buf(request.is_main());
+        buf.set_last_in_chain(true);
+
+        let rc = request.send_header();
+        if rc == Status::NGX_ERROR || rc > Status::NGX_OK || request.header_only() {
+            return rc;
+        }
+
+        let mut out = ngx_chain_t {
+            buf: buf.as_ngx_buf_mut(),
+            next: ptr::null_mut(),
+        };
+        request.output_filter(&mut out)
+    }
+}
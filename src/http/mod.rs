@@ -1,10 +1,16 @@
 mod conf;
+mod file;
 mod module;
 mod request;
+#[cfg(feature = "std")]
+mod request_ref;
 mod status;
 mod upstream;
 
 pub use conf::*;
+pub use file::*;
 pub use module::*;
 pub use request::*;
+#[cfg(feature = "std")]
+pub use request_ref::*;
 pub use status::*;
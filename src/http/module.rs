@@ -77,7 +77,7 @@ pub trait HTTPModule {
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
     unsafe extern "C" fn create_main_conf(cf: *mut ngx_conf_t) -> *mut c_void {
-        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        let pool = Pool::from_ngx_conf(&*cf);
         pool.allocate::<Self::MainConf>(Default::default()) as *mut c_void
     }
 
@@ -94,7 +94,7 @@ pub trait HTTPModule {
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
     unsafe extern "C" fn create_srv_conf(cf: *mut ngx_conf_t) -> *mut c_void {
-        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        let pool = Pool::from_ngx_conf(&*cf);
         pool.allocate::<Self::SrvConf>(Default::default()) as *mut c_void
     }
 
@@ -116,7 +116,7 @@ pub trait HTTPModule {
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
     unsafe extern "C" fn create_loc_conf(cf: *mut ngx_conf_t) -> *mut c_void {
-        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        let pool = Pool::from_ngx_conf(&*cf);
         pool.allocate::<Self::LocConf>(Default::default()) as *mut c_void
     }
 
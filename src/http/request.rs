@@ -1,10 +1,10 @@
 use core::ffi::c_void;
 use core::fmt;
-use core::slice;
 use core::str::FromStr;
 
 use crate::core::*;
 use crate::ffi::*;
+use crate::http::conf::*;
 use crate::http::status::*;
 
 /// Define a static request handler.
@@ -119,7 +119,7 @@ impl Request {
     }
 
     /// Request pool.
-    pub fn pool(&self) -> Pool {
+    pub fn pool(&self) -> &Pool {
         // SAFETY: This request is allocated from `pool`, thus must be a valid pool.
         unsafe { Pool::from_ngx_pool(self.0.pool) }
     }
@@ -145,6 +145,20 @@ impl Request {
         self.0.connection
     }
 
+    /// Client [`Connection`] of the request.
+    ///
+    /// Subrequests share the connection with the main request.
+    pub fn client_connection(&self) -> &Connection {
+        // SAFETY: a request is always associated with a valid client connection
+        unsafe { Connection::from_ngx_connection(self.0.connection) }
+    }
+
+    /// Mutable reference to the client [`Connection`] of the request.
+    pub fn client_connection_mut(&mut self) -> &mut Connection {
+        // SAFETY: a request is always associated with a valid client connection
+        unsafe { Connection::from_ngx_connection(self.0.connection) }
+    }
+
     /// Pointer to a [`ngx_log_t`].
     ///
     /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
@@ -158,7 +172,7 @@ impl Request {
     ///
     /// # Safety
     /// Caller must ensure that type `T` matches the configuration type for the specified module.
-    pub fn get_module_main_conf<T>(&self, module: &ngx_module_t) -> Option<&'static T> {
+    pub fn get_module_main_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
         // SAFETY: main conf is either NULL or allocated with ngx_p(c)alloc and
         // explicitly initialized by the module
         unsafe {
@@ -173,7 +187,7 @@ impl Request {
     ///
     /// # Safety
     /// Caller must ensure that type `T` matches the configuration type for the specified module.
-    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&'static T> {
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
         // SAFETY: server conf is either NULL or allocated with ngx_p(c)alloc and
         // explicitly initialized by the module
         unsafe {
@@ -188,7 +202,7 @@ impl Request {
     ///
     /// # Safety
     /// Caller must ensure that type `T` matches the configuration type for the specified module.
-    pub fn get_module_loc_conf<T>(&self, module: &ngx_module_t) -> Option<&'static T> {
+    pub fn get_module_loc_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
         // SAFETY: location conf is either NULL or allocated with ngx_p(c)alloc and
         // explicitly initialized by the module
         unsafe {
@@ -197,6 +211,21 @@ impl Request {
         }
     }
 
+    /// Core module configuration of the `server` block selected for the request.
+    pub fn core_srv_conf(&self) -> &CoreSrvConf {
+        self.get_module_srv_conf(unsafe { &*core::ptr::addr_of!(ngx_http_core_module) })
+            .expect("core server configuration")
+    }
+
+    /// Core module configuration of the `location` block matched for the request.
+    ///
+    /// Before the location is found (e.g. in the `NGX_HTTP_SERVER_REWRITE_PHASE`), this is the
+    /// configuration of the `server` block.
+    pub fn core_loc_conf(&self) -> &CoreLocConf {
+        self.get_module_loc_conf(unsafe { &*core::ptr::addr_of!(ngx_http_core_module) })
+            .expect("core location configuration")
+    }
+
     /// Get Module context pointer
     fn get_module_ctx_ptr(&self, module: &ngx_module_t) -> *mut c_void {
         unsafe { *self.0.ctx.add(module.ctx_index) }
@@ -282,6 +311,51 @@ impl Request {
         self.0.headers_out.content_length_n = n as off_t;
     }
 
+    /// Set response [Last-Modified] time.
+    ///
+    /// The time is used by the not modified filter to handle `If-Modified-Since` and
+    /// `If-Unmodified-Since` request headers, and by the range filter for `If-Range`.
+    ///
+    /// [Last-Modified]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Last-Modified
+    pub fn set_last_modified_time(&mut self, time: time_t) {
+        self.0.headers_out.last_modified_time = time;
+    }
+
+    /// Allow the range filter to respond with partial content to [Range] requests.
+    ///
+    /// The response must have a known length, see [`Request::set_content_length_n`].
+    ///
+    /// [Range]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Range
+    pub fn set_allow_ranges(&mut self, allow: bool) {
+        self.0.set_allow_ranges(if allow { 1 } else { 0 });
+    }
+
+    /// Set response [ETag] based on the last modified time and the content length, the same way
+    /// as nginx does for static files.
+    ///
+    /// The tag is used by the not modified filter to handle `If-None-Match` and `If-Match`
+    /// request headers. Does nothing if the `etag` directive is disabled in the location.
+    ///
+    /// [ETag]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/ETag
+    pub fn set_etag(&mut self) -> Status {
+        unsafe { Status(ngx_http_set_etag(&mut self.0)) }
+    }
+
+    /// Convert the response [ETag] into a weak one.
+    ///
+    /// Should be called when the response content is modified, e.g. by a filter module.
+    ///
+    /// [ETag]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/ETag
+    pub fn weaken_etag(&mut self) {
+        unsafe { ngx_http_weak_etag(&mut self.0) }
+    }
+
+    /// Set response `Content-Type` based on the extension of the request URI and the `types`
+    /// configuration of the location.
+    pub fn set_content_type(&mut self) -> Status {
+        unsafe { Status(ngx_http_set_content_type(&mut self.0)) }
+    }
+
     /// Send the output header.
     ///
     /// Do not call this function until all output headers are set.
@@ -422,24 +496,7 @@ impl fmt::Debug for Request {
 /// Iterator for [`ngx_list_t`] types.
 ///
 /// Implementes the core::iter::Iterator trait.
-pub struct NgxListIterator<'a> {
-    part: Option<ListPart<'a>>,
-    i: ngx_uint_t,
-}
-struct ListPart<'a> {
-    raw: &'a ngx_list_part_t,
-    arr: &'a [ngx_table_elt_t],
-}
-impl<'a> From<&'a ngx_list_part_t> for ListPart<'a> {
-    fn from(raw: &'a ngx_list_part_t) -> Self {
-        let arr = if raw.nelts != 0 {
-            unsafe { slice::from_raw_parts(raw.elts.cast(), raw.nelts) }
-        } else {
-            &[]
-        };
-        Self { raw, arr }
-    }
-}
+pub struct NgxListIterator<'a>(NgxListIter<'a, ngx_table_elt_t>);
 
 /// Creates new HTTP header iterator
 ///
@@ -447,10 +504,7 @@ impl<'a> From<&'a ngx_list_part_t> for ListPart<'a> {
 ///
 /// The caller has provided a valid [`ngx_str_t`] which can be dereferenced validly.
 pub unsafe fn list_iterator(list: &ngx_list_t) -> NgxListIterator {
-    NgxListIterator {
-        part: Some((&list.part).into()),
-        i: 0,
-    }
+    NgxListIterator(NgxList::<ngx_table_elt_t>::from_ngx_list(list).iter())
 }
 
 // iterator for ngx_list_t
@@ -462,19 +516,7 @@ impl<'a> Iterator for NgxListIterator<'a> {
     type Item = (&'a str, &'a str);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let part = self.part.as_mut()?;
-        if self.i >= part.arr.len() {
-            if let Some(next_part_raw) = unsafe { part.raw.next.as_ref() } {
-                // loop back
-                *part = next_part_raw.into();
-                self.i = 0;
-            } else {
-                self.part = None;
-                return None;
-            }
-        }
-        let header = &part.arr[self.i];
-        self.i += 1;
+        let header = self.0.next()?;
         Some((header.key.to_str(), header.value.to_str()))
     }
 }
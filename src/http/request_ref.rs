@@ -0,0 +1,435 @@
+use core::cell::{Cell, RefCell};
+use core::ffi::c_void;
+use core::future::Future;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+use core::task::{Context, Poll, Waker};
+
+use std::rc::Rc;
+use std::vec::Vec;
+
+use crate::allocator::{Allocator, Layout};
+use crate::core::Status;
+use crate::event::{JoinHandle, TaskCanceler};
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Request};
+
+/// Reference to a request, keeping it alive for a future spawned on the event loop.
+///
+/// The reference increments the reference count of the main request, `r->main->count`, as
+/// `ngx_http_read_client_request_body` does, and releases it with
+/// [`finalize`](RequestRef::finalize) or when dropped. The handler creating the reference must
+/// return `NGX_DONE` to finish the request processing asynchronously.
+///
+/// NGINX frees the request regardless of the reference count if the request is terminated, e.g. on
+/// a client abort, a timeout or an error. The future spawned with [`RequestRef::spawn`] is canceled
+/// in this case; a terminated request cannot be accessed through the reference.
+///
+/// The reference can only be used on the worker thread.
+///
+/// See <https://nginx.org/en/docs/dev/development_guide.html#http_request_finalization>
+pub struct RequestRef {
+    request: NonNull<ngx_http_request_t>,
+    state: Rc<RequestRefState>,
+}
+
+/// State of a [`RequestRef`], shared with the request cleanup handler.
+struct RequestRefState {
+    terminated: Cell<bool>,
+    /// The task to cancel if the request is terminated.
+    task: RefCell<Option<TaskCanceler>>,
+    /// State of the [`ReadBody`] future reading the request body.
+    read_body: RefCell<Option<ReadBodyState>>,
+}
+
+impl RequestRef {
+    /// Creates a reference to the request.
+    ///
+    /// Returns `None` if the allocation of the request cleanup handler fails.
+    pub fn new(request: &mut Request) -> Option<Self> {
+        let r: *mut ngx_http_request_t = request.into();
+
+        let state = Rc::new(RequestRefState {
+            terminated: Cell::new(false),
+            task: RefCell::new(None),
+            read_body: RefCell::new(None),
+        });
+
+        unsafe {
+            let cln = ngx_http_cleanup_add(r, mem::size_of::<Rc<RequestRefState>>());
+            if cln.is_null() {
+                return None;
+            }
+
+            ptr::write((*cln).data.cast(), state.clone());
+            (*cln).handler = Some(request_ref_cleanup);
+
+            let main = (*r).main;
+            (*main).set_count((*main).count() + 1);
+
+            Some(Self {
+                request: NonNull::new_unchecked(r),
+                state,
+            })
+        }
+    }
+
+    /// Returns a raw pointer to the underlying `ngx_http_request_t`.
+    pub fn as_ngx_http_request(&self) -> *mut ngx_http_request_t {
+        self.request.as_ptr()
+    }
+
+    /// Returns `true` if the request is terminated and cannot be accessed anymore.
+    pub fn is_terminated(&self) -> bool {
+        self.state.terminated.get()
+    }
+
+    /// Spawns the future created by `f` from the reference with [`spawn`](crate::event::spawn).
+    ///
+    /// The task is canceled if the request is terminated before the future completes.
+    ///
+    /// Must be called on the worker thread.
+    pub fn spawn<F, Fut>(self, f: F) -> JoinHandle<Fut::Output>
+    where
+        F: FnOnce(Self) -> Fut,
+        Fut: Future + 'static,
+        Fut::Output: 'static,
+    {
+        let state = self.state.clone();
+        let handle = crate::event::spawn(f(self));
+        // the task is polled after returning to the event loop
+        *state.task.borrow_mut() = Some(handle.canceler());
+        handle
+    }
+
+    /// Finalizes the request with `rc`, e.g. the status returned from sending the response or an
+    /// HTTP error code, and releases the reference.
+    ///
+    /// Does nothing if the request is terminated.
+    pub fn finalize(self, rc: ngx_int_t) {
+        let r = self.request.as_ptr();
+        let terminated = self.release();
+        mem::forget(self);
+
+        if !terminated {
+            unsafe { finalize_request(r, rc) };
+        }
+    }
+
+    /// Detaches the reference from the task, returning `true` if the request is terminated.
+    fn release(&self) -> bool {
+        drop(self.state.task.take());
+        self.state.terminated.get()
+    }
+
+    /// Returns a future reading the client request body, as `ngx_http_read_client_request_body`
+    /// does.
+    ///
+    /// The body is available in `request_body` of the request once the future completes. Fails with
+    /// the HTTP status code to finalize the request with.
+    ///
+    /// Must be awaited on the worker thread.
+    pub fn read_body(&mut self) -> ReadBody<'_> {
+        ReadBody {
+            request: self,
+            started: false,
+        }
+    }
+
+    /// Returns a future running a subrequest to `uri` with the `args`, and completing with the
+    /// subrequest status.
+    ///
+    /// With `in_memory`, the response body of the subrequest is returned instead of being sent to
+    /// the client, as with `NGX_HTTP_SUBREQUEST_IN_MEMORY`.
+    ///
+    /// Dropping the future does not cancel the subrequest.
+    ///
+    /// Must be awaited on the worker thread.
+    pub fn subrequest(&mut self, uri: &str, args: Option<&str>, in_memory: bool) -> Subrequest<'_> {
+        Subrequest {
+            request: self,
+            uri: uri.as_bytes().to_vec(),
+            args: args.map(|x| x.as_bytes().to_vec()),
+            in_memory,
+            state: None,
+        }
+    }
+}
+
+impl Deref for RequestRef {
+    type Target = Request;
+
+    /// # Panics
+    /// Panics if the request is terminated.
+    fn deref(&self) -> &Self::Target {
+        assert!(!self.is_terminated(), "request is terminated");
+        unsafe { Request::from_ngx_http_request(self.request.as_ptr()) }
+    }
+}
+
+impl DerefMut for RequestRef {
+    /// # Panics
+    /// Panics if the request is terminated.
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        assert!(!self.is_terminated(), "request is terminated");
+        unsafe { Request::from_ngx_http_request(self.request.as_ptr()) }
+    }
+}
+
+impl Drop for RequestRef {
+    fn drop(&mut self) {
+        if !self.release() {
+            unsafe { finalize_request(self.request.as_ptr(), Status::NGX_DONE.into()) };
+        }
+    }
+}
+
+/// Finalizes the request and runs the posted subrequests, as the connection event handlers do.
+unsafe fn finalize_request(r: *mut ngx_http_request_t, rc: ngx_int_t) {
+    let c = (*r).connection;
+    ngx_http_finalize_request(r, rc);
+    ngx_http_run_posted_requests(c);
+}
+
+/// Marks the references as terminated and cancels the task, called when the request is freed.
+unsafe extern "C" fn request_ref_cleanup(data: *mut c_void) {
+    let state = ptr::read(data.cast::<Rc<RequestRefState>>());
+    state.terminated.set(true);
+
+    if let Some(task) = state.task.take() {
+        task.cancel();
+    }
+}
+
+/// State of a [`ReadBody`] future, shared with the body handler.
+struct ReadBodyState {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// Future reading the client request body, created with [`RequestRef::read_body`].
+pub struct ReadBody<'a> {
+    request: &'a mut RequestRef,
+    started: bool,
+}
+
+impl Future for ReadBody<'_> {
+    type Output = Result<(), HTTPStatus>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let r = this.request.as_ngx_http_request();
+        let state = &this.request.state;
+
+        if !this.started {
+            assert!(!this.request.is_terminated(), "request is terminated");
+            this.started = true;
+            *state.read_body.borrow_mut() = Some(ReadBodyState {
+                done: false,
+                waker: None,
+            });
+
+            // the body handler can be called before returning
+            let rc = unsafe { ngx_http_read_client_request_body(r, Some(read_body_handler)) };
+            if rc >= NGX_HTTP_SPECIAL_RESPONSE as ngx_int_t {
+                state.read_body.take();
+                return Poll::Ready(Err(HTTPStatus(rc as _)));
+            }
+        }
+
+        let mut read_body = state.read_body.borrow_mut();
+        match read_body.as_mut() {
+            Some(read_body) if !read_body.done => {
+                read_body.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            _ => {
+                *read_body = None;
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl Drop for ReadBody<'_> {
+    fn drop(&mut self) {
+        // the body handler does not wake the dropped future
+        self.request.state.read_body.take();
+    }
+}
+
+unsafe extern "C" fn read_body_handler(r: *mut ngx_http_request_t) {
+    // the state is found with the cleanup handlers of the references to the request
+    let mut cln = (*(*r).main).cleanup;
+    while !cln.is_null() {
+        if (*cln).handler.map(|h| h as *const ()) == Some(request_ref_cleanup as *const ()) {
+            let state = &*(*cln).data.cast::<Rc<RequestRefState>>();
+
+            if let Some(read_body) = state.read_body.borrow_mut().as_mut() {
+                read_body.done = true;
+                if let Some(waker) = read_body.waker.take() {
+                    waker.wake();
+                }
+                break;
+            }
+        }
+        cln = (*cln).next;
+    }
+
+    // releases the reference taken by ngx_http_read_client_request_body()
+    ngx_http_finalize_request(r, Status::NGX_DONE.into());
+}
+
+/// Result of a subrequest, returned by a [`Subrequest`] future.
+#[derive(Debug)]
+pub struct SubrequestOutput {
+    /// The code the subrequest is finalized with.
+    pub rc: ngx_int_t,
+    /// The response status of the subrequest.
+    pub status: ngx_uint_t,
+    /// The response body of an in-memory subrequest.
+    pub body: Vec<u8>,
+}
+
+/// State of a [`Subrequest`] future, shared with the post subrequest handler.
+struct SubrequestState {
+    output: Option<SubrequestOutput>,
+    waker: Option<Waker>,
+}
+
+/// Future running a subrequest, created with [`RequestRef::subrequest`].
+pub struct Subrequest<'a> {
+    request: &'a mut RequestRef,
+    uri: Vec<u8>,
+    args: Option<Vec<u8>>,
+    in_memory: bool,
+    state: Option<Rc<RefCell<SubrequestState>>>,
+}
+
+impl Subrequest<'_> {
+    /// Creates the subrequest, returning the state shared with the post subrequest handler.
+    fn start(&mut self) -> Result<Rc<RefCell<SubrequestState>>, Status> {
+        let r = self.request.as_ngx_http_request();
+        let pool = self.request.pool();
+
+        let state = Rc::new(RefCell::new(SubrequestState {
+            output: None,
+            waker: None,
+        }));
+
+        unsafe {
+            let mut uri = ngx_str_t::from_bytes(pool.as_ngx_pool(), &self.uri).ok_or(Status::NGX_ERROR)?;
+            let mut args = match self.args {
+                Some(ref args) => Some(ngx_str_t::from_bytes(pool.as_ngx_pool(), args).ok_or(Status::NGX_ERROR)?),
+                None => None,
+            };
+
+            // the handler can be called after the future is dropped; the state is kept by the pool
+            let data = Allocator::allocate(pool, Layout::new::<Rc<RefCell<SubrequestState>>>())
+                .map_err(|_| Status::NGX_ERROR)?
+                .cast::<Rc<RefCell<SubrequestState>>>()
+                .as_ptr();
+            let cln = ngx_pool_cleanup_add(pool.as_ngx_pool(), 0);
+            let ps = pool.calloc_type::<ngx_http_post_subrequest_t>();
+            if cln.is_null() || ps.is_null() {
+                return Err(Status::NGX_ERROR);
+            }
+
+            ptr::write(data, state.clone());
+            (*cln).handler = Some(drop_subrequest_state);
+            (*cln).data = data.cast();
+
+            (*ps).handler = Some(subrequest_handler);
+            (*ps).data = data.cast();
+
+            let mut flags = NGX_HTTP_SUBREQUEST_WAITED;
+            if self.in_memory {
+                flags |= NGX_HTTP_SUBREQUEST_IN_MEMORY;
+            }
+
+            let mut sr: *mut ngx_http_request_t = ptr::null_mut();
+            let rc = ngx_http_subrequest(
+                r,
+                &mut uri,
+                args.as_mut().map_or(ptr::null_mut(), |x| x as *mut _),
+                &mut sr,
+                ps,
+                flags as _,
+            );
+            if rc != Status::NGX_OK.into() {
+                return Err(Status(rc));
+            }
+
+            // the subrequest is posted to the main request and runs with the posted requests
+            ngx_http_run_posted_requests((*r).connection);
+        }
+
+        Ok(state)
+    }
+}
+
+impl Future for Subrequest<'_> {
+    type Output = Result<SubrequestOutput, Status>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = match self.state {
+            Some(ref state) => state.clone(),
+            None => match self.start() {
+                Ok(state) => {
+                    self.state = Some(state.clone());
+                    state
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            },
+        };
+
+        let mut state = state.borrow_mut();
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(Ok(output));
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+unsafe extern "C" fn subrequest_handler(r: *mut ngx_http_request_t, data: *mut c_void, rc: ngx_int_t) -> ngx_int_t {
+    let state = &*data.cast::<Rc<RefCell<SubrequestState>>>();
+
+    // the response body is only kept for the in-memory subrequests
+    let mut body = Vec::new();
+    let mut cl = if (*r).subrequest_in_memory() != 0 {
+        (*r).out
+    } else {
+        ptr::null_mut()
+    };
+    while !cl.is_null() {
+        let b = (*cl).buf;
+        if !(*b).pos.is_null() && (*b).in_file() == 0 {
+            body.extend_from_slice(core::slice::from_raw_parts(
+                (*b).pos,
+                (*b).last.offset_from((*b).pos) as usize,
+            ));
+        }
+        cl = (*cl).next;
+    }
+
+    // the handler is called again if the subrequest is finalized more than once
+    let mut state = state.borrow_mut();
+    state.output = Some(SubrequestOutput {
+        rc,
+        status: (*r).headers_out.status,
+        body,
+    });
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+
+    rc
+}
+
+unsafe extern "C" fn drop_subrequest_state(data: *mut c_void) {
+    ptr::drop_in_place(data.cast::<Rc<RefCell<SubrequestState>>>());
+}
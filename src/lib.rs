@@ -40,6 +40,12 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+/// The allocator module.
+///
+/// This module provides the [Allocator API](https://doc.rust-lang.org/std/alloc/trait.Allocator.html)
+/// types for the collections allocated from the NGINX memory pools, see [`core::Pool`].
+pub mod allocator;
+
 /// The core module.
 ///
 /// This module provides fundamental utilities needed to interface with many NGINX primitives.
@@ -47,6 +53,13 @@ extern crate std;
 /// utilities will generally align with the NGINX 'core' files and APIs.
 pub mod core;
 
+/// The event module.
+///
+/// This module provides wrappers for scheduling work on the NGINX event loop of the current worker
+/// process, such as timers and posted events, including from the other threads, and a single-threaded
+/// executor for the async Rust code.
+pub mod event;
+
 /// The ffi module.
 ///
 /// This module provides scoped FFI bindings for NGINX symbols.